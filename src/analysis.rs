@@ -0,0 +1,571 @@
+use std::fmt::Display;
+
+use crate::chunk::Chunk;
+use crate::error::Result;
+use crate::ihdr::ColorType;
+use crate::image::Image;
+use crate::png::Png;
+
+/// Chunk types registered with the PNG spec, including APNG's
+const STANDARD_TYPES: [[u8; 4]; 25] = [
+    *b"IHDR", *b"PLTE", *b"IDAT", *b"IEND", *b"tRNS", *b"cHRM", *b"gAMA", *b"iCCP", *b"sBIT",
+    *b"sRGB", *b"cICP", *b"mDCV", *b"cLLI", *b"tEXt", *b"zTXt", *b"iTXt", *b"bKGD", *b"hIST",
+    *b"pHYs", *b"sPLT", *b"eXIf", *b"tIME", *b"acTL", *b"fcTL", *b"fdAT",
+];
+
+/// Checkpoints for the chi-square test, as twentieths of the samples
+const CHI_SQUARE_STEPS: usize = 20;
+/// Fewer samples than this say too little about the LSBs to score
+const MIN_SAMPLES: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnalysisOptions {
+    /// Ancillary chunks with more data than this are flagged
+    pub max_ancillary_length: usize,
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> AnalysisOptions {
+        AnalysisOptions { max_ancillary_length: 64 * 1024 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    /// A chunk whose type has the private bit set
+    PrivateChunk,
+    /// A public chunk type the PNG spec does not define
+    NonStandardChunk,
+    /// Bytes after the IEND chunk
+    TrailingData,
+    OversizedChunk,
+    /// Westfeld and Pfitzmann's test for pairs of values equalized by LSB
+    /// replacement. Smooth, synthetic images can score high without any
+    /// hidden data.
+    ChiSquare,
+    /// Fridrich's regular/singular groups estimate of the share of samples
+    /// whose LSB was changed
+    RsAnalysis,
+}
+
+impl Check {
+    pub fn name(self) -> &'static str {
+        match self {
+            Check::PrivateChunk => "private-chunk",
+            Check::NonStandardChunk => "non-standard-chunk",
+            Check::TrailingData => "trailing-data",
+            Check::OversizedChunk => "oversized-chunk",
+            Check::ChiSquare => "chi-square",
+            Check::RsAnalysis => "rs-analysis",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub check: Check,
+    /// From 0 (nothing unusual) to 1 (almost certainly hiding data)
+    pub score: f64,
+    pub detail: String,
+}
+
+/// Everything `analyze` noticed. Structural findings only appear when
+/// something was found; the pixel tests always report, unless the image
+/// cannot be tested.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Report {
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    /// The highest score of any finding
+    pub fn score(&self) -> f64 {
+        self.findings.iter().map(|finding| finding.score).fold(0.0, f64::max)
+    }
+
+    pub fn verdict(&self) -> &'static str {
+        match self.score() {
+            score if score >= 0.8 => "hidden data likely",
+            score if score >= 0.4 => "suspicious",
+            _ => "nothing found",
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let findings: Vec<String> = self
+            .findings
+            .iter()
+            .map(|finding| {
+                format!(
+                    "{{\"check\":\"{}\",\"score\":{:.4},\"detail\":{}}}",
+                    finding.check.name(),
+                    finding.score,
+                    json_string(&finding.detail)
+                )
+            })
+            .collect();
+        format!(
+            "{{\"score\":{:.4},\"verdict\":\"{}\",\"findings\":[{}]}}",
+            self.score(),
+            self.verdict(),
+            findings.join(",")
+        )
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} (score {:.2})", self.verdict(), self.score())?;
+        for finding in self.findings.iter() {
+            writeln!(f, "  {:<19} {:.2}  {}", finding.check.name(), finding.score, finding.detail)?;
+        }
+        Ok(())
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Looks for signs of hidden data in a whole PNG file. Only a file that
+/// does not parse is an error.
+pub fn analyze(bytes: &[u8], options: &AnalysisOptions) -> Result<Report> {
+    let png = Png::try_from(bytes)?;
+    let mut report = Report::default();
+    for chunk in png.chunks().iter() {
+        check_chunk(chunk, options, &mut report);
+    }
+    if !png.trailer().is_empty() {
+        report.findings.push(Finding {
+            check: Check::TrailingData,
+            score: 1.0,
+            detail: format!("{} bytes after IEND", png.trailer().len()),
+        });
+    }
+
+    match png.decode_image() {
+        Ok(_) if png.ihdr()?.color_type == ColorType::Indexed => {}
+        Ok(image) if image.bit_depth >= 8 => {
+            report.findings.push(chi_square_finding(&image));
+            report.findings.push(rs_finding(&image));
+        }
+        _ => {}
+    }
+    Ok(report)
+}
+
+fn check_chunk(chunk: &Chunk, options: &AnalysisOptions, report: &mut Report) {
+    let chunk_type = chunk.chunk_type();
+    if !chunk_type.is_public() {
+        report.findings.push(Finding {
+            check: Check::PrivateChunk,
+            score: 0.7,
+            detail: format!("{} chunk, {} bytes", chunk_type, chunk.length()),
+        });
+    } else if !STANDARD_TYPES.contains(&chunk_type.bytes()) {
+        report.findings.push(Finding {
+            check: Check::NonStandardChunk,
+            score: 0.5,
+            detail: format!("{} chunk, {} bytes", chunk_type, chunk.length()),
+        });
+    }
+    if !chunk_type.is_critical() && chunk.data().len() > options.max_ancillary_length {
+        report.findings.push(Finding {
+            check: Check::OversizedChunk,
+            score: 0.6,
+            detail: format!(
+                "{} chunk holds {} bytes, over the {} byte limit",
+                chunk_type,
+                chunk.length(),
+                options.max_ancillary_length
+            ),
+        });
+    }
+}
+
+/// Channels that carry color, leaving out alpha
+fn color_channels(image: &Image) -> usize {
+    image.layout.channels() - image.layout.has_alpha() as usize
+}
+
+/// Result of the chi-square test on the LSBs of the color samples
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChiSquare {
+    /// Probability that the pairs of values differing only in their LSB are
+    /// as evenly filled as in a fully embedded image; the highest over
+    /// every prefix checked
+    pub p_value: f64,
+    /// Share of the samples, from the start, up to the last prefix whose
+    /// p-value is above 0.5. Sequential embedding shows up as a prefix about
+    /// as long as the message.
+    pub embedded_fraction: f64,
+}
+
+/// Runs the chi-square test over growing prefixes of the color samples, in
+/// twentieths, since sequential embedding only equalizes the start of the
+/// image. Returns `None` when there are too few samples.
+pub fn chi_square(image: &Image) -> Option<ChiSquare> {
+    let channels = color_channels(image);
+    let total = image.width as usize * image.height as usize * channels;
+    if total < MIN_SAMPLES {
+        return None;
+    }
+    let mut histogram = [0u64; 256];
+    let mut result = ChiSquare { p_value: 0.0, embedded_fraction: 0.0 };
+    let mut counted = 0;
+    let mut step = 1;
+    for y in 0..image.height {
+        for x in 0..image.width {
+            for channel in 0..channels {
+                histogram[(image.sample(x, y, channel) & 0xff) as usize] += 1;
+                counted += 1;
+                if counted * CHI_SQUARE_STEPS < step * total {
+                    continue;
+                }
+                let p_value = chi_square_p_value(&histogram).unwrap_or(0.0);
+                result.p_value = result.p_value.max(p_value);
+                if p_value > 0.5 {
+                    result.embedded_fraction = step as f64 / CHI_SQUARE_STEPS as f64;
+                }
+                step += 1;
+            }
+        }
+    }
+    Some(result)
+}
+
+/// Upper tail probability of the chi-square statistic comparing each even
+/// value's count against the mean of its pair, if enough pairs are filled
+fn chi_square_p_value(histogram: &[u64; 256]) -> Option<f64> {
+    let mut statistic = 0.0;
+    let mut pairs = 0;
+    for pair in histogram.chunks(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        // Sparse pairs make the approximation meaningless
+        if expected < 5.0 {
+            continue;
+        }
+        statistic += (pair[0] as f64 - expected).powi(2) / expected;
+        pairs += 1;
+    }
+    if pairs < 2 {
+        return None;
+    }
+    Some(1.0 - lower_regularized_gamma((pairs - 1) as f64 / 2.0, statistic / 2.0))
+}
+
+fn chi_square_finding(image: &Image) -> Finding {
+    match chi_square(image) {
+        Some(result) => Finding {
+            check: Check::ChiSquare,
+            score: result.p_value,
+            detail: format!(
+                "p = {:.3}, LSB pairs equalized over the first {:.0}% of samples",
+                result.p_value,
+                result.embedded_fraction * 100.0
+            ),
+        },
+        None => Finding {
+            check: Check::ChiSquare,
+            score: 0.0,
+            detail: String::from("too few samples to test"),
+        },
+    }
+}
+
+/// Natural log of the gamma function, by the Lanczos approximation
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .fold(1.000000000190015, |sum, (index, c)| sum + c / (x + 1.0 + index as f64));
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+/// P(a, x), the regularized lower incomplete gamma function: by its series
+/// below `a + 1` and by a continued fraction above
+fn lower_regularized_gamma(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let prefix = (-x + a * x.ln() - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        let (mut term, mut sum, mut n) = (1.0 / a, 1.0 / a, a);
+        for _ in 0..1000 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-15 {
+                break;
+            }
+        }
+        (sum * prefix).min(1.0)
+    } else {
+        // Lentz's method
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..1000 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            d = if d.abs() < tiny { 1.0 / tiny } else { 1.0 / d };
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < 1e-15 {
+                break;
+            }
+        }
+        (1.0 - prefix * h).max(0.0)
+    }
+}
+
+/// Shares of regular and singular groups after flipping the masked samples
+/// with `F1` (`negative` false) or `F-1` (`negative` true)
+fn rs_counts(groups: &[[i32; 4]], negative: bool) -> (f64, f64) {
+    const MASK: [bool; 4] = [false, true, true, false];
+    let smoothness = |group: &[i32; 4]| -> i32 {
+        group.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum()
+    };
+    let (mut regular, mut singular) = (0usize, 0usize);
+    for group in groups {
+        let mut flipped = *group;
+        for (value, &masked) in flipped.iter_mut().zip(MASK.iter()) {
+            if masked {
+                *value = if negative { ((*value + 1) ^ 1) - 1 } else { *value ^ 1 };
+            }
+        }
+        match smoothness(&flipped).cmp(&smoothness(group)) {
+            std::cmp::Ordering::Greater => regular += 1,
+            std::cmp::Ordering::Less => singular += 1,
+            std::cmp::Ordering::Equal => {}
+        }
+    }
+    let total = groups.len() as f64;
+    (regular as f64 / total, singular as f64 / total)
+}
+
+/// Estimates the share of color samples whose LSB was changed, from 0 to 1,
+/// by RS analysis over groups of four horizontally adjacent samples of the
+/// same channel. Returns `None` when there are too few samples or the
+/// image is too flat for an estimate.
+pub fn rs_analysis(image: &Image) -> Option<f64> {
+    let channels = color_channels(image);
+    let mut groups = Vec::new();
+    for y in 0..image.height {
+        for channel in 0..channels {
+            for x in (0..image.width.saturating_sub(3)).step_by(4) {
+                groups.push([0, 1, 2, 3].map(|offset| image.sample(x + offset, y, channel) as i32));
+            }
+        }
+    }
+    if groups.len() * 4 < MIN_SAMPLES {
+        return None;
+    }
+    let flipped: Vec<[i32; 4]> = groups.iter().map(|group| group.map(|value| value ^ 1)).collect();
+
+    let (r_m, s_m) = rs_counts(&groups, false);
+    let (r_neg, s_neg) = rs_counts(&groups, true);
+    let (r_m_flipped, s_m_flipped) = rs_counts(&flipped, false);
+    let (r_neg_flipped, s_neg_flipped) = rs_counts(&flipped, true);
+    let d0 = r_m - s_m;
+    let d1 = r_m_flipped - s_m_flipped;
+    let d_neg0 = r_neg - s_neg;
+    let d_neg1 = r_neg_flipped - s_neg_flipped;
+
+    // 2(d1 + d0)z^2 + (d-0 - d-1 - d1 - 3d0)z + d0 - d-0 = 0
+    let a = 2.0 * (d1 + d0);
+    let b = d_neg0 - d_neg1 - d1 - 3.0 * d0;
+    let c = d0 - d_neg0;
+    let z = if a.abs() < 1e-12 {
+        if b.abs() < 1e-12 {
+            return None;
+        }
+        -c / b
+    } else {
+        // Close to full embedding the roots can turn complex; their real
+        // part is still the best estimate
+        let root = (b * b - 4.0 * a * c).max(0.0).sqrt();
+        let (first, second) = ((-b + root) / (2.0 * a), (-b - root) / (2.0 * a));
+        if first.abs() < second.abs() {
+            first
+        } else {
+            second
+        }
+    };
+    let rate = z / (z - 0.5);
+    rate.is_finite().then(|| rate.clamp(0.0, 1.0))
+}
+
+fn rs_finding(image: &Image) -> Finding {
+    match rs_analysis(image) {
+        // Clean images come out within a few percent of zero
+        Some(rate) => Finding {
+            check: Check::RsAnalysis,
+            score: ((rate - 0.05) / 0.25).clamp(0.0, 1.0),
+            detail: format!("about {:.0}% of sample LSBs changed", rate * 100.0),
+        },
+        None => Finding {
+            check: Check::RsAnalysis,
+            score: 0.0,
+            detail: String::from("no estimate, the image is too small or too flat"),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use crate::error::Error;
+    use crate::png::tests::PNG_FILE;
+    use crate::png::InsertionPolicy;
+    use crate::stego::{self, LsbOptions};
+    use std::str::FromStr;
+
+    fn random_bytes(length: usize) -> Vec<u8> {
+        let mut state = 0x1234_5678u32;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn finding(report: &Report, check: Check) -> &Finding {
+        report.findings.iter().find(|finding| finding.check == check).unwrap()
+    }
+
+    #[test]
+    fn test_clean_image() {
+        let report = analyze(&PNG_FILE, &AnalysisOptions::default()).unwrap();
+        let checks: Vec<Check> = report.findings.iter().map(|finding| finding.check).collect();
+        // RuSt is the private chunk the sample file carries
+        assert_eq!(checks, vec![Check::PrivateChunk, Check::ChiSquare, Check::RsAnalysis]);
+        assert!(finding(&report, Check::ChiSquare).score < 0.1);
+        assert!(finding(&report, Check::RsAnalysis).score < 0.1);
+        assert_eq!(report.verdict(), "suspicious");
+    }
+
+    #[test]
+    fn test_lsb_embedding_is_detected() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.remove_chunk("RuSt").unwrap();
+        let clean = analyze(&png.as_bytes(), &AnalysisOptions::default()).unwrap();
+        assert_eq!(clean.verdict(), "nothing found");
+
+        let options = LsbOptions::default();
+        let image = png.decode_image().unwrap();
+        let message = random_bytes(stego::capacity(&image, &options).unwrap() / 2);
+        stego::hide(&mut png, &message, &options).unwrap();
+        let report = analyze(&png.as_bytes(), &AnalysisOptions::default()).unwrap();
+        let chi_square = chi_square(&png.decode_image().unwrap()).unwrap();
+        assert!(chi_square.p_value > 0.99);
+        assert!((0.4..=0.7).contains(&chi_square.embedded_fraction));
+        assert!(finding(&report, Check::RsAnalysis).score > 0.5);
+        assert_eq!(report.verdict(), "hidden data likely");
+    }
+
+    #[test]
+    fn test_rs_estimates_rate() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut image = png.decode_image().unwrap();
+        let options = LsbOptions {
+            key: Some(stego::LsbKey::from_bytes([3; 32])),
+            ..Default::default()
+        };
+        let message = random_bytes(stego::capacity(&image, &options).unwrap());
+        stego::embed(&mut image, &message, &options).unwrap();
+        // Random bits leave about half the LSBs of the color samples as they were
+        assert!(rs_analysis(&image).unwrap() > 0.8);
+    }
+
+    #[test]
+    fn test_structural_findings() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let big = Chunk::new(ChunkType::from_str("tEXt").unwrap(), vec![b'a'; 70_000]);
+        png.insert_chunk(big, InsertionPolicy::Auto);
+        let unknown = Chunk::new(ChunkType::from_str("xBCd").unwrap(), vec![1, 2, 3]);
+        png.insert_chunk(unknown, InsertionPolicy::Auto);
+        let mut bytes = png.as_bytes();
+        bytes.extend_from_slice(b"appended after the end");
+
+        let report = analyze(&bytes, &AnalysisOptions::default()).unwrap();
+        assert_eq!(finding(&report, Check::TrailingData).detail, "22 bytes after IEND");
+        assert!(finding(&report, Check::OversizedChunk).detail.starts_with("tEXt"));
+        assert!(finding(&report, Check::NonStandardChunk).detail.starts_with("xBCd"));
+        assert_eq!(report.score(), 1.0);
+
+        let options = AnalysisOptions { max_ancillary_length: 100_000 };
+        let report = analyze(&bytes, &options).unwrap();
+        assert!(report.findings.iter().all(|finding| finding.check != Check::OversizedChunk));
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        assert_eq!(analyze(b"not a png", &Default::default()), Err(Error::InvalidSignature));
+        let mut bytes = PNG_FILE.to_vec();
+        bytes[41] ^= 0xff;
+        assert!(matches!(
+            analyze(&bytes, &Default::default()),
+            Err(Error::CrcMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_chi_square_distribution() {
+        // With two degrees of freedom the upper tail is e^(-x/2)
+        let p = 1.0 - lower_regularized_gamma(1.0, 1.0);
+        assert!((p - (-1.0f64).exp()).abs() < 1e-9);
+        // The familiar 5% critical value for one degree of freedom
+        let p = 1.0 - lower_regularized_gamma(0.5, 3.841 / 2.0);
+        assert!((p - 0.05).abs() < 1e-3);
+        let p = 1.0 - lower_regularized_gamma(50.0, 20.0);
+        assert!(p > 0.999999);
+    }
+
+    #[test]
+    fn test_json() {
+        let report = Report {
+            findings: vec![Finding {
+                check: Check::TrailingData,
+                score: 1.0,
+                detail: String::from("a \"quoted\"\nline"),
+            }],
+        };
+        assert_eq!(
+            report.to_json(),
+            "{\"score\":1.0000,\"verdict\":\"hidden data likely\",\"findings\":[\
+             {\"check\":\"trailing-data\",\"score\":1.0000,\
+             \"detail\":\"a \\\"quoted\\\"\\u000aline\"}]}"
+        );
+    }
+}
@@ -0,0 +1,786 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{Error, Result};
+use crate::ihdr::ColorType;
+
+/// Typed view of a standard PLTE or ancillary chunk. Use `KnownChunk::parse`
+/// to get one from a `Chunk` and `KnownChunk::to_chunk` to go back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KnownChunk {
+    Plte(Plte),
+    Trns(Trns),
+    Gama(Gama),
+    Chrm(Chrm),
+    Srgb(Srgb),
+    Iccp(Iccp),
+    Phys(Phys),
+    Time(Time),
+    Bkgd(Bkgd),
+    Sbit(Sbit),
+    Hist(Hist),
+    Splt(Splt),
+}
+
+impl KnownChunk {
+    /// Parses `chunk` if its type is one of the standard chunks handled here,
+    /// returning `Ok(None)` for any other type. tRNS, bKGD and sBIT are laid
+    /// out differently per color type, so the image's color type is needed.
+    pub fn parse(chunk: &Chunk, color_type: ColorType) -> Result<Option<KnownChunk>> {
+        let data = chunk.data();
+        let known = match &chunk.chunk_type().bytes() {
+            b"PLTE" => KnownChunk::Plte(Plte::parse(data)?),
+            b"tRNS" => KnownChunk::Trns(Trns::parse(data, color_type)?),
+            b"gAMA" => KnownChunk::Gama(Gama::parse(data)?),
+            b"cHRM" => KnownChunk::Chrm(Chrm::parse(data)?),
+            b"sRGB" => KnownChunk::Srgb(Srgb::parse(data)?),
+            b"iCCP" => KnownChunk::Iccp(Iccp::parse(data)?),
+            b"pHYs" => KnownChunk::Phys(Phys::parse(data)?),
+            b"tIME" => KnownChunk::Time(Time::parse(data)?),
+            b"bKGD" => KnownChunk::Bkgd(Bkgd::parse(data, color_type)?),
+            b"sBIT" => KnownChunk::Sbit(Sbit::parse(data, color_type)?),
+            b"hIST" => KnownChunk::Hist(Hist::parse(data)?),
+            b"sPLT" => KnownChunk::Splt(Splt::parse(data)?),
+            _ => return Ok(None),
+        };
+        Ok(Some(known))
+    }
+
+    pub fn chunk_type(&self) -> [u8; 4] {
+        match self {
+            KnownChunk::Plte(_) => *b"PLTE",
+            KnownChunk::Trns(_) => *b"tRNS",
+            KnownChunk::Gama(_) => *b"gAMA",
+            KnownChunk::Chrm(_) => *b"cHRM",
+            KnownChunk::Srgb(_) => *b"sRGB",
+            KnownChunk::Iccp(_) => *b"iCCP",
+            KnownChunk::Phys(_) => *b"pHYs",
+            KnownChunk::Time(_) => *b"tIME",
+            KnownChunk::Bkgd(_) => *b"bKGD",
+            KnownChunk::Sbit(_) => *b"sBIT",
+            KnownChunk::Hist(_) => *b"hIST",
+            KnownChunk::Splt(_) => *b"sPLT",
+        }
+    }
+
+    /// Fails if the value could not be parsed back, e.g. an sPLT name that
+    /// is not a valid keyword
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let bytes = match self {
+            KnownChunk::Plte(plte) => plte.to_bytes(),
+            KnownChunk::Trns(trns) => trns.to_bytes(),
+            KnownChunk::Gama(gama) => gama.to_bytes(),
+            KnownChunk::Chrm(chrm) => chrm.to_bytes(),
+            KnownChunk::Srgb(srgb) => srgb.to_bytes(),
+            KnownChunk::Iccp(iccp) => iccp.to_bytes()?,
+            KnownChunk::Phys(phys) => phys.to_bytes(),
+            KnownChunk::Time(time) => time.to_bytes(),
+            KnownChunk::Bkgd(bkgd) => bkgd.to_bytes(),
+            KnownChunk::Sbit(sbit) => sbit.to_bytes(),
+            KnownChunk::Hist(hist) => hist.to_bytes(),
+            KnownChunk::Splt(splt) => splt.to_bytes()?,
+        };
+        Ok(bytes)
+    }
+
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        let chunk_type = ChunkType::try_from(self.chunk_type()).unwrap();
+        Chunk::try_new(chunk_type, self.to_bytes()?)
+    }
+}
+
+fn invalid(chunk_type: &str, reason: &'static str) -> Error {
+    Error::InvalidChunkData {
+        chunk_type: chunk_type.to_string(),
+        reason,
+    }
+}
+
+fn read_u16(data: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([data[at], data[at + 1]])
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+/// Decodes ISO 8859-1 text, which maps every byte straight to a code point
+pub(crate) fn latin1_to_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
+}
+
+/// Encodes text as ISO 8859-1, or `None` if it has characters outside it
+pub(crate) fn string_to_latin1(text: &str) -> Option<Vec<u8>> {
+    text.chars().map(|char| u8::try_from(char as u32).ok()).collect()
+}
+
+/// Checks the keyword rules shared by iCCP, sPLT and the text chunks:
+/// 1 to 79 printable Latin-1 characters with no leading, trailing or
+/// consecutive spaces
+pub(crate) fn validate_keyword(keyword: &[u8]) -> std::result::Result<(), &'static str> {
+    if keyword.is_empty() || keyword.len() > 79 {
+        return Err("keyword must be 1 to 79 bytes long");
+    }
+    if keyword.iter().any(|&byte| !(32..=126).contains(&byte) && byte < 161) {
+        return Err("keyword must only contain printable Latin-1 characters");
+    }
+    let misplaced_space = keyword.starts_with(b" ")
+        || keyword.ends_with(b" ")
+        || keyword.windows(2).any(|pair| pair == b"  ");
+    if misplaced_space {
+        return Err("keyword must not have leading, trailing or consecutive spaces");
+    }
+    Ok(())
+}
+
+/// Splits `data` at the null byte ending a keyword and validates the keyword
+fn split_keyword<'a>(chunk_type: &str, data: &'a [u8]) -> Result<(String, &'a [u8])> {
+    let end = data
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or(invalid(chunk_type, "keyword is not null terminated"))?;
+    validate_keyword(&data[..end]).map_err(|reason| invalid(chunk_type, reason))?;
+    Ok((latin1_to_string(&data[..end]), &data[end + 1..]))
+}
+
+/// Encodes a keyword followed by its null terminator, checking it the way
+/// `split_keyword` will when it is read back
+fn keyword_bytes(chunk_type: &str, keyword: &str) -> Result<Vec<u8>> {
+    let mut bytes = string_to_latin1(keyword).ok_or(invalid(
+        chunk_type,
+        "keyword must only contain printable Latin-1 characters",
+    ))?;
+    validate_keyword(&bytes).map_err(|reason| invalid(chunk_type, reason))?;
+    bytes.push(0);
+    Ok(bytes)
+}
+
+/// PLTE: the palette, 1 to 256 RGB entries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plte {
+    pub entries: Vec<[u8; 3]>,
+}
+
+impl Plte {
+    pub fn parse(data: &[u8]) -> Result<Plte> {
+        if data.is_empty() || !data.len().is_multiple_of(3) || data.len() > 256 * 3 {
+            return Err(invalid("PLTE", "palette must hold 1 to 256 three-byte entries"));
+        }
+        let entries = data.chunks_exact(3).map(|entry| [entry[0], entry[1], entry[2]]).collect();
+        Ok(Plte { entries })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.entries.concat()
+    }
+}
+
+/// tRNS: simple transparency, whose layout depends on the color type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trns {
+    /// The one gray level that is fully transparent
+    Gray(u16),
+    /// The one color that is fully transparent
+    Rgb { red: u16, green: u16, blue: u16 },
+    /// Alpha for the first palette entries, the rest are opaque
+    Palette(Vec<u8>),
+}
+
+impl Trns {
+    pub fn parse(data: &[u8], color_type: ColorType) -> Result<Trns> {
+        match color_type {
+            ColorType::Grayscale if data.len() == 2 => Ok(Trns::Gray(read_u16(data, 0))),
+            ColorType::Rgb if data.len() == 6 => Ok(Trns::Rgb {
+                red: read_u16(data, 0),
+                green: read_u16(data, 2),
+                blue: read_u16(data, 4),
+            }),
+            ColorType::Indexed if data.len() <= 256 => Ok(Trns::Palette(data.to_vec())),
+            ColorType::GrayscaleAlpha | ColorType::Rgba => {
+                Err(invalid("tRNS", "not allowed for color types with an alpha channel"))
+            }
+            _ => Err(invalid("tRNS", "wrong length for the color type")),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Trns::Gray(gray) => gray.to_be_bytes().to_vec(),
+            Trns::Rgb { red, green, blue } => [*red, *green, *blue]
+                .iter()
+                .flat_map(|sample| sample.to_be_bytes())
+                .collect(),
+            Trns::Palette(alphas) => alphas.clone(),
+        }
+    }
+}
+
+/// gAMA: image gamma times 100000
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gama {
+    pub gamma: u32,
+}
+
+impl Gama {
+    pub fn parse(data: &[u8]) -> Result<Gama> {
+        if data.len() != 4 {
+            return Err(invalid("gAMA", "must be 4 bytes long"));
+        }
+        Ok(Gama { gamma: read_u32(data, 0) })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.gamma.to_be_bytes().to_vec()
+    }
+}
+
+/// cHRM: chromaticities of the white point and primaries, times 100000
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chrm {
+    pub white_x: u32,
+    pub white_y: u32,
+    pub red_x: u32,
+    pub red_y: u32,
+    pub green_x: u32,
+    pub green_y: u32,
+    pub blue_x: u32,
+    pub blue_y: u32,
+}
+
+impl Chrm {
+    pub fn parse(data: &[u8]) -> Result<Chrm> {
+        if data.len() != 32 {
+            return Err(invalid("cHRM", "must be 32 bytes long"));
+        }
+        Ok(Chrm {
+            white_x: read_u32(data, 0),
+            white_y: read_u32(data, 4),
+            red_x: read_u32(data, 8),
+            red_y: read_u32(data, 12),
+            green_x: read_u32(data, 16),
+            green_y: read_u32(data, 20),
+            blue_x: read_u32(data, 24),
+            blue_y: read_u32(data, 28),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [
+            self.white_x,
+            self.white_y,
+            self.red_x,
+            self.red_y,
+            self.green_x,
+            self.green_y,
+            self.blue_x,
+            self.blue_y,
+        ]
+        .iter()
+        .flat_map(|value| value.to_be_bytes())
+        .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderingIntent {
+    Perceptual,
+    RelativeColorimetric,
+    Saturation,
+    AbsoluteColorimetric,
+}
+
+/// sRGB: the image is in the sRGB color space
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Srgb {
+    pub intent: RenderingIntent,
+}
+
+impl Srgb {
+    pub fn parse(data: &[u8]) -> Result<Srgb> {
+        let intent = match data {
+            [0] => RenderingIntent::Perceptual,
+            [1] => RenderingIntent::RelativeColorimetric,
+            [2] => RenderingIntent::Saturation,
+            [3] => RenderingIntent::AbsoluteColorimetric,
+            [_] => return Err(invalid("sRGB", "unknown rendering intent")),
+            _ => return Err(invalid("sRGB", "must be 1 byte long")),
+        };
+        Ok(Srgb { intent })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        vec![self.intent as u8]
+    }
+}
+
+/// iCCP: an embedded ICC profile, kept zlib-compressed as stored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Iccp {
+    pub name: String,
+    pub compressed_profile: Vec<u8>,
+}
+
+impl Iccp {
+    pub fn parse(data: &[u8]) -> Result<Iccp> {
+        let (name, rest) = split_keyword("iCCP", data)?;
+        match rest.split_first() {
+            Some((0, profile)) => Ok(Iccp {
+                name,
+                compressed_profile: profile.to_vec(),
+            }),
+            Some(_) => Err(invalid("iCCP", "unknown compression method")),
+            None => Err(invalid("iCCP", "compression method is missing")),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = keyword_bytes("iCCP", &self.name)?;
+        bytes.push(0);
+        bytes.extend_from_slice(&self.compressed_profile);
+        Ok(bytes)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhysUnit {
+    /// Only the aspect ratio is known
+    Unknown,
+    Meter,
+}
+
+/// pHYs: intended pixel size or aspect ratio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Phys {
+    pub pixels_per_unit_x: u32,
+    pub pixels_per_unit_y: u32,
+    pub unit: PhysUnit,
+}
+
+impl Phys {
+    pub fn parse(data: &[u8]) -> Result<Phys> {
+        if data.len() != 9 {
+            return Err(invalid("pHYs", "must be 9 bytes long"));
+        }
+        let unit = match data[8] {
+            0 => PhysUnit::Unknown,
+            1 => PhysUnit::Meter,
+            _ => return Err(invalid("pHYs", "unknown unit")),
+        };
+        Ok(Phys {
+            pixels_per_unit_x: read_u32(data, 0),
+            pixels_per_unit_y: read_u32(data, 4),
+            unit,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.pixels_per_unit_x.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&self.pixels_per_unit_y.to_be_bytes());
+        bytes.push(self.unit as u8);
+        bytes
+    }
+}
+
+/// tIME: last modification time, in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Time {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    /// Up to 60 to allow for leap seconds
+    pub second: u8,
+}
+
+impl Time {
+    pub fn parse(data: &[u8]) -> Result<Time> {
+        if data.len() != 7 {
+            return Err(invalid("tIME", "must be 7 bytes long"));
+        }
+        let time = Time {
+            year: read_u16(data, 0),
+            month: data[2],
+            day: data[3],
+            hour: data[4],
+            minute: data[5],
+            second: data[6],
+        };
+        let in_range = (1..=12).contains(&time.month)
+            && (1..=31).contains(&time.day)
+            && time.hour <= 23
+            && time.minute <= 59
+            && time.second <= 60;
+        if !in_range {
+            return Err(invalid("tIME", "date or time field out of range"));
+        }
+        Ok(time)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.year.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&[self.month, self.day, self.hour, self.minute, self.second]);
+        bytes
+    }
+}
+
+/// bKGD: suggested background color, whose layout depends on the color type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bkgd {
+    Gray(u16),
+    Rgb { red: u16, green: u16, blue: u16 },
+    PaletteIndex(u8),
+}
+
+impl Bkgd {
+    pub fn parse(data: &[u8], color_type: ColorType) -> Result<Bkgd> {
+        match (color_type, data.len()) {
+            (ColorType::Grayscale | ColorType::GrayscaleAlpha, 2) => {
+                Ok(Bkgd::Gray(read_u16(data, 0)))
+            }
+            (ColorType::Rgb | ColorType::Rgba, 6) => Ok(Bkgd::Rgb {
+                red: read_u16(data, 0),
+                green: read_u16(data, 2),
+                blue: read_u16(data, 4),
+            }),
+            (ColorType::Indexed, 1) => Ok(Bkgd::PaletteIndex(data[0])),
+            _ => Err(invalid("bKGD", "wrong length for the color type")),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Bkgd::Gray(gray) => gray.to_be_bytes().to_vec(),
+            Bkgd::Rgb { red, green, blue } => [*red, *green, *blue]
+                .iter()
+                .flat_map(|sample| sample.to_be_bytes())
+                .collect(),
+            Bkgd::PaletteIndex(index) => vec![*index],
+        }
+    }
+}
+
+/// sBIT: significant bits per channel in the original image. Holds one
+/// value per channel, with indexed images counting as RGB.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sbit {
+    pub significant_bits: Vec<u8>,
+}
+
+impl Sbit {
+    pub fn parse(data: &[u8], color_type: ColorType) -> Result<Sbit> {
+        let expected = match color_type {
+            ColorType::Indexed => 3,
+            other => other.channels(),
+        };
+        if data.len() != expected {
+            return Err(invalid("sBIT", "wrong length for the color type"));
+        }
+        if data.iter().any(|&bits| bits == 0 || bits > 16) {
+            return Err(invalid("sBIT", "significant bits must be between 1 and 16"));
+        }
+        Ok(Sbit { significant_bits: data.to_vec() })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.significant_bits.clone()
+    }
+}
+
+/// hIST: approximate usage frequency of each palette entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hist {
+    pub frequencies: Vec<u16>,
+}
+
+impl Hist {
+    pub fn parse(data: &[u8]) -> Result<Hist> {
+        if data.is_empty() || !data.len().is_multiple_of(2) || data.len() > 512 {
+            return Err(invalid("hIST", "must hold 1 to 256 two-byte entries"));
+        }
+        let frequencies = data.chunks_exact(2).map(|pair| read_u16(pair, 0)).collect();
+        Ok(Hist { frequencies })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.frequencies.iter().flat_map(|frequency| frequency.to_be_bytes()).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpltEntry {
+    pub red: u16,
+    pub green: u16,
+    pub blue: u16,
+    pub alpha: u16,
+    pub frequency: u16,
+}
+
+/// sPLT: a suggested palette for viewers with limited colors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Splt {
+    pub name: String,
+    /// 8 or 16; samples never exceed this depth
+    pub sample_depth: u8,
+    pub entries: Vec<SpltEntry>,
+}
+
+impl Splt {
+    pub fn parse(data: &[u8]) -> Result<Splt> {
+        let (name, rest) = split_keyword("sPLT", data)?;
+        let (&sample_depth, entries) =
+            rest.split_first().ok_or(invalid("sPLT", "sample depth is missing"))?;
+        let entry_length = match sample_depth {
+            8 => 6,
+            16 => 10,
+            _ => return Err(invalid("sPLT", "sample depth must be 8 or 16")),
+        };
+        if !entries.len().is_multiple_of(entry_length) {
+            return Err(invalid("sPLT", "palette entries are truncated"));
+        }
+
+        let entries = entries
+            .chunks_exact(entry_length)
+            .map(|entry| {
+                if sample_depth == 8 {
+                    SpltEntry {
+                        red: entry[0] as u16,
+                        green: entry[1] as u16,
+                        blue: entry[2] as u16,
+                        alpha: entry[3] as u16,
+                        frequency: read_u16(entry, 4),
+                    }
+                } else {
+                    SpltEntry {
+                        red: read_u16(entry, 0),
+                        green: read_u16(entry, 2),
+                        blue: read_u16(entry, 4),
+                        alpha: read_u16(entry, 6),
+                        frequency: read_u16(entry, 8),
+                    }
+                }
+            })
+            .collect();
+        Ok(Splt { name, sample_depth, entries })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = keyword_bytes("sPLT", &self.name)?;
+        bytes.push(self.sample_depth);
+        for entry in self.entries.iter() {
+            let samples = [entry.red, entry.green, entry.blue, entry.alpha];
+            match self.sample_depth {
+                8 => {
+                    for sample in samples {
+                        let sample = u8::try_from(sample)
+                            .map_err(|_| invalid("sPLT", "sample does not fit in 8 bits"))?;
+                        bytes.push(sample);
+                    }
+                }
+                16 => bytes.extend(samples.iter().flat_map(|sample| sample.to_be_bytes())),
+                _ => return Err(invalid("sPLT", "sample depth must be 8 or 16")),
+            }
+            bytes.extend_from_slice(&entry.frequency.to_be_bytes());
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn round_trip(known: KnownChunk, color_type: ColorType) {
+        let chunk = known.to_chunk().unwrap();
+        let parsed = KnownChunk::parse(&chunk, color_type).unwrap().unwrap();
+        assert_eq!(parsed, known);
+        assert_eq!(parsed.to_chunk().unwrap().as_bytes(), chunk.as_bytes());
+    }
+
+    #[test]
+    fn test_round_trip_every_type() {
+        let cases = vec![
+            (
+                KnownChunk::Plte(Plte { entries: vec![[0, 0, 0], [255, 128, 1]] }),
+                ColorType::Indexed,
+            ),
+            (KnownChunk::Trns(Trns::Gray(7)), ColorType::Grayscale),
+            (KnownChunk::Trns(Trns::Rgb { red: 1, green: 2, blue: 300 }), ColorType::Rgb),
+            (KnownChunk::Trns(Trns::Palette(vec![0, 128])), ColorType::Indexed),
+            (KnownChunk::Gama(Gama { gamma: 45455 }), ColorType::Rgb),
+            (
+                KnownChunk::Chrm(Chrm {
+                    white_x: 31270,
+                    white_y: 32900,
+                    red_x: 64000,
+                    red_y: 33000,
+                    green_x: 30000,
+                    green_y: 60000,
+                    blue_x: 15000,
+                    blue_y: 6000,
+                }),
+                ColorType::Rgb,
+            ),
+            (KnownChunk::Srgb(Srgb { intent: RenderingIntent::Saturation }), ColorType::Rgb),
+            (
+                KnownChunk::Iccp(Iccp {
+                    name: String::from("Display P3 ©"),
+                    compressed_profile: vec![120, 156, 3, 0, 0, 0, 0, 1],
+                }),
+                ColorType::Rgb,
+            ),
+            (
+                KnownChunk::Phys(Phys {
+                    pixels_per_unit_x: 3780,
+                    pixels_per_unit_y: 3780,
+                    unit: PhysUnit::Meter,
+                }),
+                ColorType::Rgb,
+            ),
+            (
+                KnownChunk::Time(Time {
+                    year: 2024,
+                    month: 2,
+                    day: 29,
+                    hour: 23,
+                    minute: 59,
+                    second: 60,
+                }),
+                ColorType::Rgb,
+            ),
+            (KnownChunk::Bkgd(Bkgd::Gray(255)), ColorType::GrayscaleAlpha),
+            (KnownChunk::Bkgd(Bkgd::Rgb { red: 1, green: 2, blue: 3 }), ColorType::Rgba),
+            (KnownChunk::Bkgd(Bkgd::PaletteIndex(4)), ColorType::Indexed),
+            (KnownChunk::Sbit(Sbit { significant_bits: vec![5, 6, 5] }), ColorType::Indexed),
+            (KnownChunk::Sbit(Sbit { significant_bits: vec![4, 4] }), ColorType::GrayscaleAlpha),
+            (KnownChunk::Hist(Hist { frequencies: vec![0, 1, 65535] }), ColorType::Indexed),
+            (
+                KnownChunk::Splt(Splt {
+                    name: String::from("web safe"),
+                    sample_depth: 8,
+                    entries: vec![SpltEntry {
+                        red: 0,
+                        green: 51,
+                        blue: 255,
+                        alpha: 255,
+                        frequency: 9,
+                    }],
+                }),
+                ColorType::Rgb,
+            ),
+            (
+                KnownChunk::Splt(Splt {
+                    name: String::from("deep"),
+                    sample_depth: 16,
+                    entries: vec![SpltEntry {
+                        red: 1000,
+                        green: 2000,
+                        blue: 65535,
+                        alpha: 0,
+                        frequency: 1,
+                    }],
+                }),
+                ColorType::Rgb,
+            ),
+        ];
+
+        for (known, color_type) in cases {
+            round_trip(known, color_type);
+        }
+    }
+
+    #[test]
+    fn test_dice_image_chunks() {
+        // sRGB, gAMA and pHYs chunks copied from the dice test image
+        let srgb = Chunk::new(ChunkType::from_str("sRGB").unwrap(), vec![0]);
+        let gama = Chunk::new(ChunkType::from_str("gAMA").unwrap(), vec![0, 0, 177, 143]);
+        let phys = Chunk::new(
+            ChunkType::from_str("pHYs").unwrap(),
+            vec![0, 0, 14, 194, 0, 0, 14, 194, 1],
+        );
+
+        assert_eq!(
+            KnownChunk::parse(&srgb, ColorType::Rgba).unwrap(),
+            Some(KnownChunk::Srgb(Srgb { intent: RenderingIntent::Perceptual }))
+        );
+        assert_eq!(
+            KnownChunk::parse(&gama, ColorType::Rgba).unwrap(),
+            Some(KnownChunk::Gama(Gama { gamma: 45455 }))
+        );
+        assert_eq!(
+            KnownChunk::parse(&phys, ColorType::Rgba).unwrap(),
+            Some(KnownChunk::Phys(Phys {
+                pixels_per_unit_x: 3778,
+                pixels_per_unit_y: 3778,
+                unit: PhysUnit::Meter
+            }))
+        );
+    }
+
+    #[test]
+    fn test_unknown_type_is_none() {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"message".to_vec());
+        assert_eq!(KnownChunk::parse(&chunk, ColorType::Rgb).unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        let cases: Vec<(&str, Vec<u8>, ColorType)> = vec![
+            ("PLTE", vec![1, 2], ColorType::Indexed),
+            ("tRNS", vec![0, 1], ColorType::Rgba),
+            ("tRNS", vec![0, 1, 2], ColorType::Grayscale),
+            ("gAMA", vec![0, 1], ColorType::Rgb),
+            ("sRGB", vec![4], ColorType::Rgb),
+            ("iCCP", b"no terminator".to_vec(), ColorType::Rgb),
+            ("iCCP", b" leading space\0\0".to_vec(), ColorType::Rgb),
+            ("pHYs", vec![0, 0, 0, 1, 0, 0, 0, 1, 2], ColorType::Rgb),
+            ("tIME", vec![7, 232, 13, 1, 0, 0, 0], ColorType::Rgb),
+            ("bKGD", vec![1], ColorType::Rgb),
+            ("sBIT", vec![0, 8, 8], ColorType::Rgb),
+            ("hIST", vec![1, 2, 3], ColorType::Indexed),
+            ("sPLT", b"name\0\x07".to_vec(), ColorType::Rgb),
+        ];
+
+        for (chunk_type, data, color_type) in cases {
+            let chunk = Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data);
+            let result = KnownChunk::parse(&chunk, color_type);
+            assert!(
+                matches!(result, Err(Error::InvalidChunkData { .. })),
+                "{} should be rejected",
+                chunk_type
+            );
+        }
+    }
+
+    #[test]
+    fn test_unserializable_values() {
+        let entry = SpltEntry { red: 256, green: 0, blue: 0, alpha: 255, frequency: 1 };
+        let splt = |name: &str, sample_depth| {
+            KnownChunk::Splt(Splt { name: String::from(name), sample_depth, entries: vec![entry] })
+        };
+        let iccp = |name: &str| {
+            KnownChunk::Iccp(Iccp { name: String::from(name), compressed_profile: vec![] })
+        };
+
+        assert!(splt("deep", 16).to_chunk().is_ok());
+        let cases = [splt("deep", 8), splt("deep", 4), splt("日本", 16), iccp("日本"), iccp("")];
+        for known in cases {
+            assert!(
+                matches!(known.to_chunk(), Err(Error::InvalidChunkData { .. })),
+                "{:?} should be rejected",
+                known
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_keyword() {
+        assert!(validate_keyword(b"Author").is_ok());
+        assert!(validate_keyword(b"Two words").is_ok());
+        assert!(validate_keyword(b"").is_err());
+        assert!(validate_keyword(&[b'a'; 80]).is_err());
+        assert!(validate_keyword(b"double  space").is_err());
+        assert!(validate_keyword(b"trailing ").is_err());
+        assert!(validate_keyword(b"tab\there").is_err());
+    }
+}
@@ -0,0 +1,344 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use rust_png_cryptex::compression;
+
+/// Hide and recover messages inside the chunks of a PNG file
+#[derive(Debug, Parser)]
+#[command(name = "pngme", version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Store a message in a new chunk of the given type, or in the pixels
+    Encode(EncodeArgs),
+    /// Print the message stored in a chunk of the given type, or in the pixels
+    Decode(DecodeArgs),
+    /// Remove a chunk of the given type
+    Remove(RemoveArgs),
+    /// List every chunk in the file
+    Print(PrintArgs),
+    /// Generate an X25519 identity for receiving encrypted messages, or an
+    /// Ed25519 key for signing
+    Keygen(KeygenArgs),
+    /// Sign the critical chunks and the message chunks, i.e. every private
+    /// ancillary chunk
+    Sign(SignArgs),
+    /// Check the signature and list which chunks it covers
+    Verify(VerifyArgs),
+    /// List, read, set or delete tEXt, zTXt and iTXt entries
+    Text(TextArgs),
+    /// List or delete the EXIF tags in the eXIf chunk
+    Exif(ExifArgs),
+    /// Remove metadata and message chunks, keeping what is needed to render
+    Scrub(ScrubArgs),
+    /// Look for signs of hidden data and print a scored report
+    Analyze(AnalyzeArgs),
+    /// Show, extract, strip or write the bytes after IEND
+    Trailer(TrailerArgs),
+}
+
+/// Where the message is hidden
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Mode {
+    /// In a chunk of its own
+    Chunk,
+    /// In the least significant bits of the pixel samples
+    Lsb,
+}
+
+#[derive(Debug, Args)]
+pub struct LsbArgs {
+    /// Low bits of each sample to use in lsb mode
+    #[arg(long, default_value_t = 1)]
+    pub bits: u8,
+    /// Channels to use in lsb mode, by index within a pixel (e.g. 0,1,2 for
+    /// red, green and blue); defaults to every channel except alpha
+    #[arg(long, value_name = "INDEX", value_delimiter = ',')]
+    pub channels: Vec<u8>,
+    /// Scatter the message over the pixels in an order derived from this
+    /// passphrase, in lsb mode
+    #[arg(long, value_name = "PASSPHRASE")]
+    pub key: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct EncodeArgs {
+    /// PNG file to read
+    pub file: PathBuf,
+    /// CHUNK_TYPE MESSAGE [OUTPUT]. CHUNK_TYPE is left out in lsb mode and
+    /// MESSAGE with --file. The chunk type is four letters, e.g. ruSt, and
+    /// OUTPUT defaults to overwriting `file`
+    #[arg(value_names = ["CHUNK_TYPE", "MESSAGE", "OUTPUT"], num_args = 0..=3)]
+    pub arguments: Vec<String>,
+    /// Hide this file, with its name and type, instead of a text message
+    #[arg(long = "file", value_name = "PATH")]
+    pub payload_file: Option<PathBuf>,
+    /// MIME type to record for --file, guessed from its extension by default
+    #[arg(long, value_name = "TYPE", requires = "payload_file")]
+    pub mime: Option<String>,
+    #[arg(long, value_enum, default_value_t = Mode::Chunk)]
+    pub mode: Mode,
+    #[command(flatten)]
+    pub lsb: LsbArgs,
+    /// Encrypt the message with this passphrase; in chunk mode the chunk
+    /// type must be ancillary and private, e.g. crYp
+    #[arg(long, conflicts_with = "recipients")]
+    pub password: Option<String>,
+    /// Encrypt the message to this public key, may be repeated; in chunk mode
+    /// the chunk type must be ancillary and private, e.g. reCp
+    #[arg(long = "recipient", value_name = "PUBLIC_KEY")]
+    pub recipients: Vec<String>,
+    /// Put the chunk last, right before IEND, even if it is not safe to copy
+    /// and would otherwise go before the first IDAT
+    #[arg(long)]
+    pub append: bool,
+    /// Deflate the message before encrypting and embedding it
+    #[arg(long)]
+    pub compress: bool,
+}
+
+/// The positional arguments of `encode`, sorted out by mode
+pub struct EncodeTarget<'a> {
+    pub chunk_type: Option<&'a str>,
+    /// `None` when hiding a file
+    pub message: Option<&'a str>,
+    pub output: Option<PathBuf>,
+}
+
+impl EncodeArgs {
+    pub fn target(&self) -> Result<EncodeTarget<'_>, String> {
+        let mut arguments = self.arguments.iter().map(String::as_str);
+        let chunk_type = match self.mode {
+            Mode::Chunk => Some(
+                arguments.next().ok_or_else(|| String::from("chunk mode needs a chunk type"))?,
+            ),
+            Mode::Lsb => None,
+        };
+        let message = match self.payload_file {
+            Some(_) => None,
+            None => Some(
+                arguments.next().ok_or_else(|| String::from("give a message or --file"))?,
+            ),
+        };
+        let output = arguments.next().map(PathBuf::from);
+        if arguments.next().is_some() {
+            return Err(String::from("too many arguments"));
+        }
+        Ok(EncodeTarget { chunk_type, message, output })
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct DecodeArgs {
+    pub file: PathBuf,
+    /// Required in chunk mode
+    pub chunk_type: Option<String>,
+    #[arg(long, value_enum, default_value_t = Mode::Chunk)]
+    pub mode: Mode,
+    #[command(flatten)]
+    pub lsb: LsbArgs,
+    /// Which chunk of that type to read, counting from 0
+    #[arg(long, default_value_t = 0)]
+    pub index: usize,
+    /// Print every chunk of that type, starting at --index
+    #[arg(long)]
+    pub all: bool,
+    /// Passphrase the message was encrypted with
+    #[arg(long, conflicts_with = "identity")]
+    pub password: Option<String>,
+    /// File holding the secret key, as written by `keygen`
+    #[arg(long)]
+    pub identity: Option<PathBuf>,
+    /// Refuse to decompress messages that would be larger than this
+    #[arg(long, value_name = "BYTES", default_value_t = compression::DEFAULT_LIMIT)]
+    pub max_size: usize,
+    /// Restore hidden files into this directory, under their original names
+    #[arg(long, value_name = "DIR")]
+    pub out_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct RemoveArgs {
+    pub file: PathBuf,
+    pub chunk_type: String,
+    /// Which chunk of that type to remove, counting from 0
+    #[arg(long, default_value_t = 0, conflicts_with = "all")]
+    pub index: usize,
+    /// Remove every chunk of that type
+    #[arg(long)]
+    pub all: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct PrintArgs {
+    pub file: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct KeygenArgs {
+    /// Generate an Ed25519 signing key instead of an X25519 identity
+    #[arg(long)]
+    pub signing: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct SignArgs {
+    pub file: PathBuf,
+    /// File holding the signing key, as written by `keygen --signing`
+    #[arg(long)]
+    pub key: PathBuf,
+    /// Also sign every chunk of this type, may be repeated. Critical and
+    /// private ancillary chunks are always signed
+    #[arg(long = "message-type", value_name = "CHUNK_TYPE")]
+    pub message_types: Vec<String>,
+    /// Where to write the result, defaults to overwriting `file`
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    pub file: PathBuf,
+    /// Public key the file must be signed by; the key stored in the
+    /// signature itself is not trusted
+    #[arg(long, value_name = "PUBLIC_KEY")]
+    pub signer: String,
+}
+
+#[derive(Debug, Args)]
+pub struct TextArgs {
+    #[command(subcommand)]
+    pub action: TextAction,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TextAction {
+    /// Print every text entry
+    List {
+        file: PathBuf,
+    },
+    /// Print the value of the first entry with this keyword
+    Get {
+        file: PathBuf,
+        keyword: String,
+    },
+    /// Store a value under a keyword, replacing any existing entries
+    Set(TextSetArgs),
+    /// Delete every entry with this keyword
+    Delete {
+        file: PathBuf,
+        keyword: String,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct TextSetArgs {
+    pub file: PathBuf,
+    /// e.g. Author, Description, Copyright
+    pub keyword: String,
+    pub value: String,
+    /// Store the value zlib-compressed, in zTXt or iTXt
+    #[arg(long)]
+    pub compress: bool,
+    /// Store the value in iTXt with this language tag, e.g. en-GB
+    #[arg(long, value_name = "TAG")]
+    pub language: Option<String>,
+    /// Where to write the result, defaults to overwriting `file`
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ExifArgs {
+    #[command(subcommand)]
+    pub action: ExifAction,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ExifAction {
+    /// Print every tag with the IFD it is in
+    List {
+        file: PathBuf,
+    },
+    /// Delete tags by name (e.g. GPSInfo, BodySerialNumber) or number (e.g. 0xa431)
+    Delete {
+        file: PathBuf,
+        #[arg(required = true)]
+        tags: Vec<String>,
+        /// Where to write the result, defaults to overwriting `file`
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct ScrubArgs {
+    pub file: PathBuf,
+    /// Keep every chunk of this type, may be repeated
+    #[arg(long = "keep", value_name = "CHUNK_TYPE")]
+    pub keep: Vec<String>,
+    /// Remove every chunk of this type even if it would be kept, may be repeated
+    #[arg(long = "remove", value_name = "CHUNK_TYPE")]
+    pub remove: Vec<String>,
+    /// Also remove color management chunks (gAMA, cHRM, sRGB, iCCP, ...)
+    #[arg(long)]
+    pub strip_color: bool,
+    /// Only report what would be removed
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Where to write the result, defaults to overwriting `file`
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct AnalyzeArgs {
+    pub file: PathBuf,
+    /// Print the report as JSON
+    #[arg(long)]
+    pub json: bool,
+    /// Flag ancillary chunks holding more data than this
+    #[arg(long, value_name = "BYTES", default_value_t = 65536)]
+    pub max_chunk_size: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct TrailerArgs {
+    #[command(subcommand)]
+    pub action: TrailerAction,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TrailerAction {
+    /// Print the trailer's size and a hex dump of its start
+    Show {
+        file: PathBuf,
+        /// Dump the whole trailer rather than the first 256 bytes
+        #[arg(long)]
+        all: bool,
+    },
+    /// Save the trailer to a file
+    Extract {
+        file: PathBuf,
+        output: PathBuf,
+    },
+    /// Remove the trailer
+    Strip {
+        file: PathBuf,
+        /// Where to write the result, defaults to overwriting `file`
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Replace the trailer with the contents of a file
+    Write {
+        file: PathBuf,
+        input: PathBuf,
+        /// Where to write the result, defaults to overwriting `file`
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
@@ -1,8 +1,10 @@
-use std::{fmt::Display, result::Result, string::FromUtf8Error};
+use std::{fmt::Display, string::FromUtf8Error};
 
 use crate::chunk_type::ChunkType;
+use crate::error::{Error, Result};
 
 
+#[derive(Debug)]
 pub struct Chunk {
     length: u32,
     chunk_type: ChunkType,
@@ -11,71 +13,128 @@ pub struct Chunk {
 }
 
 impl TryFrom<&[u8]> for Chunk {
-    type Error = &'static str;
-
-    fn try_from(value: &[u8]) -> Result<crate::chunk::Chunk, Self::Error> {
-        if value.len() <= 0 {
-            Err("No data found")
-        } else {
-            let mut data_length: u32 = 0;
-            let base: u32 = 2;
-            for entry in 0..4 {
-                data_length += value[entry] as u32 * base.pow(3-entry as u32);
-            }
-            let mut chunk_type_bytes: [u8; 4] = [0; 4];
-            chunk_type_bytes.copy_from_slice(&value[4..8]);
+    type Error = Error;
 
-            let chunk_type = ChunkType::try_from(chunk_type_bytes).unwrap();
+    fn try_from(value: &[u8]) -> Result<Chunk> {
+        let length_bytes: [u8; 4] = read_field(value, 0, 4)?.try_into().unwrap();
+        let data_length = Chunk::parse_length(length_bytes)?;
 
-            if !chunk_type.is_valid() {
-                return Err("The chunk type is invalid");
-            }
+        let chunk_type_bytes: [u8; 4] = read_field(value, 4, 4)?.try_into().unwrap();
+        let chunk_type = Chunk::parse_type(chunk_type_bytes)?;
 
-            let chunk_data = &value[8 .. 8 + (data_length as usize)];
-            let crc_bytes : [u8; 4] = value[data_length as usize + 8 .. ][0..4].try_into().unwrap();
-            let crc = u32::from_be_bytes(crc_bytes);
+        let chunk_data = read_field(value, 8, data_length as usize)?;
+        let crc_bytes: [u8; 4] = read_field(value, 8 + data_length as usize, 4)?.try_into().unwrap();
+        let crc = u32::from_be_bytes(crc_bytes);
 
-            // Comparing with independent checksum calculation
-            const X25: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
-            let expected_crc = X25.checksum(&[&chunk_type.bytes()[..], &chunk_data[..]].concat());
+        Chunk::from_parts(chunk_type, chunk_data.into(), crc)
+    }
 
-            if expected_crc != crc {
-                return Err("CRC comparison failed.");
-            }
+}
 
-            Ok(
-                Chunk {
-                    length: data_length,
-                    chunk_type,
-                    chunk_data: chunk_data.into(),
-                    crc
-                }
-            )
-        }
-    }
+/// CRC-32 as used by PNG, computed over the chunk type and data
+pub(crate) fn checksum(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+    const X25: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+    let mut digest = X25.digest();
+    digest.update(&chunk_type.bytes());
+    digest.update(data);
+    digest.finalize()
+}
 
+/// Returns `needed` bytes starting at `offset`, or a `Truncated` error
+/// if the input ends before that
+fn read_field(value: &[u8], offset: usize, needed: usize) -> Result<&[u8]> {
+    value
+        .get(offset..offset + needed)
+        .ok_or(Error::Truncated { offset, needed })
 }
 
 impl Display for Chunk {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "({}, {})", 5, 6)
+        write!(
+            f,
+            "{} ({} bytes, crc {:#010x})",
+            self.chunk_type, self.length, self.crc
+        )
     }
 }
 
 
 impl Chunk {
-
+    /// Largest value the PNG spec allows in a chunk's length field
+    pub const MAX_LENGTH: u32 = (1 << 31) - 1;
+
+    /// Builds a chunk from data known to be small.
+    ///
+    /// # Panics
+    ///
+    /// If `data` is longer than `MAX_LENGTH`; use `try_new` for data of
+    /// any size.
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
-        let length = data.len() as u32;
-        const X25: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
-        let crc_value = X25.checksum(&[&chunk_type.bytes()[..], &data[..]].concat());
-        Chunk {
+        match Chunk::try_new(chunk_type, data) {
+            Ok(chunk) => chunk,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Builds a chunk, failing if `data` is longer than `MAX_LENGTH`
+    pub fn try_new(chunk_type: ChunkType, data: Vec<u8>) -> Result<Chunk> {
+        let length = u32::try_from(data.len())
+            .ok()
+            .filter(|&length| length <= Chunk::MAX_LENGTH)
+            .ok_or_else(|| Error::InvalidChunkData {
+                chunk_type: chunk_type.to_string(),
+                reason: "data is longer than 2^31 - 1 bytes",
+            })?;
+        let crc_value = checksum(&chunk_type, &data);
+        Ok(Chunk {
             length,
             chunk_data: data.into_boxed_slice(),
             chunk_type,
             crc: crc_value
+        })
+    }
+
+    /// Validates the length field found at the start of a chunk
+    pub(crate) fn parse_length(bytes: [u8; 4]) -> Result<u32> {
+        let length = u32::from_be_bytes(bytes);
+        if length > Chunk::MAX_LENGTH {
+            return Err(Error::LengthTooLarge { length, offset: 0 });
+        }
+        Ok(length)
+    }
+
+    /// Validates the chunk type field, which follows the length field
+    pub(crate) fn parse_type(bytes: [u8; 4]) -> Result<ChunkType> {
+        let chunk_type = ChunkType::try_from(bytes).map_err(|error| error.offset_by(4))?;
+        if !chunk_type.is_valid() {
+            return Err(Error::InvalidChunkType {
+                chunk_type: chunk_type.to_string(),
+                offset: 4
+            });
         }
+        Ok(chunk_type)
+    }
 
+    /// Builds a chunk read from a file, checking the stored CRC against its contents
+    pub(crate) fn from_parts(chunk_type: ChunkType, data: Box<[u8]>, crc: u32) -> Result<Chunk> {
+        let expected_crc = checksum(&chunk_type, &data);
+        if expected_crc != crc {
+            return Err(Error::CrcMismatch {
+                chunk_type: chunk_type.to_string(),
+                expected: expected_crc,
+                actual: crc,
+                offset: 0
+            });
+        }
+
+        Ok(
+            Chunk {
+                length: data.len() as u32,
+                chunk_type,
+                chunk_data: data,
+                crc
+            }
+        )
     }
 
     pub fn length(&self) -> u32 {
@@ -94,9 +153,8 @@ impl Chunk {
         self.crc
     }
 
-    pub fn data_as_string(&self) -> Result<String, FromUtf8Error> {
-        let result = String::from_utf8(self.chunk_data.to_vec());
-        result
+    pub fn data_as_string(&self) -> std::result::Result<String, FromUtf8Error> {
+        String::from_utf8(self.chunk_data.to_vec())
     }
 
     pub fn as_bytes(&self) -> Vec<u8> {
@@ -104,7 +162,7 @@ impl Chunk {
 
         let mut length_bytes = self.length.to_be_bytes().to_vec();
         let mut chunk_type_bytes = self.chunk_type.bytes().to_vec();
-        let mut chunk_data_bytes = self.chunk_data.to_vec().to_vec();
+        let mut chunk_data_bytes = self.chunk_data.to_vec();
         let mut crc_bytes = self.crc.to_be_bytes().to_vec();
 
         bytes.append(& mut length_bytes);
@@ -151,6 +209,18 @@ mod tests {
         assert_eq!(chunk.crc(), 2882656334);
     }
 
+    #[test]
+    fn test_new_chunk_too_long() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        // Zeroed allocations are not touched until written, so this stays cheap
+        let data = vec![0; Chunk::MAX_LENGTH as usize + 1];
+        assert!(matches!(
+            Chunk::try_new(chunk_type, data),
+            Err(Error::InvalidChunkData { .. })
+        ));
+        assert!(Chunk::try_new(ChunkType::from_str("RuSt").unwrap(), Vec::new()).is_ok());
+    }
+
     #[test]
     fn test_chunk_length() {
         let chunk = testing_chunk();
@@ -225,6 +295,58 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_crc_mismatch_error() {
+        let mut chunk_data = testing_chunk().as_bytes();
+        let last = chunk_data.len() - 1;
+        chunk_data[last] ^= 1;
+
+        let error = Chunk::try_from(chunk_data.as_ref()).unwrap_err();
+
+        assert_eq!(
+            error,
+            Error::CrcMismatch {
+                chunk_type: String::from("RuSt"),
+                expected: 2882656334,
+                actual: 2882656334 ^ 1,
+                offset: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_truncated_chunk() {
+        let chunk_data = testing_chunk().as_bytes();
+
+        for end in 0..chunk_data.len() {
+            let result = Chunk::try_from(&chunk_data[..end]);
+            assert!(matches!(result, Err(Error::Truncated { .. })));
+        }
+
+        assert_eq!(
+            Chunk::try_from(&chunk_data[..20]).unwrap_err(),
+            Error::Truncated { offset: 8, needed: 42 }
+        );
+    }
+
+    #[test]
+    fn test_chunk_length_too_large() {
+        let chunk_data = [128, 0, 0, 0, 82, 117, 83, 116];
+        assert_eq!(
+            Chunk::try_from(chunk_data.as_ref()).unwrap_err(),
+            Error::LengthTooLarge { length: 1 << 31, offset: 0 }
+        );
+    }
+
+    #[test]
+    fn test_non_letter_chunk_type() {
+        let chunk_data = [0, 0, 0, 0, 82, 0, 83, 116, 0, 0, 0, 0];
+        assert!(matches!(
+            Chunk::try_from(chunk_data.as_ref()),
+            Err(Error::InvalidChunkType { offset: 5, .. })
+        ));
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
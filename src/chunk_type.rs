@@ -1,4 +1,6 @@
-use std::{result::Result, str::{FromStr, from_utf8}, ops::BitAnd, fmt::Display};
+use std::{str::FromStr, ops::BitAnd, fmt::Display};
+
+use crate::error::{Error, Result};
 
 /// This struct stores a valid 4-byte PNG chunk type
 /// Provides methods that return the chunk type in bytes,
@@ -26,8 +28,14 @@ impl Display for ChunkType {
 }
 
 impl TryFrom<[u8; 4]> for ChunkType {
-    type Error = &'static str;
-    fn try_from(bytes: [u8; 4]) -> Result<ChunkType, Self::Error> {
+    type Error = Error;
+    fn try_from(bytes: [u8; 4]) -> Result<ChunkType> {
+        if let Some(offset) = bytes.iter().position(|byte| !byte.is_ascii_alphabetic()) {
+            return Err(Error::InvalidChunkType {
+                chunk_type: String::from_utf8_lossy(&bytes).into_owned(),
+                offset
+            });
+        }
         let (mut ancillary_bit, mut private_bit, mut reserved_bit, mut safe_to_copy_bit) = (false, false, false, false);
         for (index, value) in bytes.iter().enumerate() {
             match index {
@@ -49,17 +57,22 @@ impl TryFrom<[u8; 4]> for ChunkType {
 }
 
 impl FromStr for ChunkType {
-    type Err = &'static str;
-
-    fn from_str(val: &str) -> Result<ChunkType, Self::Err> {
+    type Err = Error;
+
+    fn from_str(val: &str) -> Result<ChunkType> {
+        let invalid = |offset| Error::InvalidChunkType {
+            chunk_type: val.to_string(),
+            offset
+        };
+        if val.len() != 4 {
+            return Err(invalid(0));
+        }
         let mut bytes : [u8; 4] = [0; 4];
-        for (index, char) in val.chars().enumerate() {
-            if matches!(char, 'a'..='z') || matches!(char, 'A'..='Z') {
-                let mut byte = [0; 1];
-                char.encode_utf8(&mut byte);
-                bytes[index] = byte[0];
+        for (index, char) in val.char_indices() {
+            if char.is_ascii_alphabetic() {
+                bytes[index] = char as u8;
             } else {
-                return Err("String input should only contain characters A-Z or a-z");
+                return Err(invalid(index));
             }
         }
         Ok(ChunkType{
@@ -184,6 +197,22 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_invalid_chunk_type_error() {
+        let error = ChunkType::from_str("Ru1t").unwrap_err();
+        assert_eq!(
+            error,
+            Error::InvalidChunkType { chunk_type: String::from("Ru1t"), offset: 2 }
+        );
+        assert!(ChunkType::from_str("RuStRuSt").is_err());
+    }
+
+    #[test]
+    pub fn test_chunk_type_from_non_letter_bytes() {
+        let chunk = ChunkType::try_from([82, 117, 0xff, 116]);
+        assert!(matches!(chunk, Err(Error::InvalidChunkType { offset: 2, .. })));
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
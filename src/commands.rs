@@ -0,0 +1,605 @@
+use std::{
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io::{BufReader, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use rust_png_cryptex::analysis::{self, AnalysisOptions};
+use rust_png_cryptex::container::FilePayload;
+use rust_png_cryptex::crypto::{self, Kdf};
+use rust_png_cryptex::exif;
+use rust_png_cryptex::payload::Message;
+use rust_png_cryptex::recipients;
+use rust_png_cryptex::signature;
+use rust_png_cryptex::stego::{self, LsbKey};
+use rust_png_cryptex::text::{TextEntry, TextKind};
+use x25519_dalek::{PublicKey, StaticSecret};
+use rust_png_cryptex::{
+    Chunk, ChunkReader, ChunkType, InsertionPolicy, LsbOptions, Png, ScrubPolicy,
+};
+
+use crate::args::{
+    AnalyzeArgs, DecodeArgs, EncodeArgs, ExifAction, ExifArgs, KeygenArgs, LsbArgs, Mode,
+    PrintArgs, RemoveArgs, ScrubArgs, SignArgs, TextAction, TextArgs, TextSetArgs, TrailerAction,
+    TrailerArgs, VerifyArgs,
+};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+fn read_png(path: &Path) -> Result<Png> {
+    let bytes = fs::read(path)
+        .map_err(|error| format!("could not read {}: {}", path.display(), error))?;
+    let png = Png::try_from(bytes.as_ref())
+        .map_err(|error| format!("{} is not a valid PNG: {}", path.display(), error))?;
+    Ok(png)
+}
+
+fn write_png(path: &Path, png: &Png) -> Result<()> {
+    fs::write(path, png.as_bytes())
+        .map_err(|error| format!("could not write {}: {}", path.display(), error))?;
+    Ok(())
+}
+
+pub fn encode(args: EncodeArgs) -> Result<()> {
+    let target = args.target()?;
+    let message = match (&args.payload_file, target.message) {
+        (Some(path), _) => {
+            let data = fs::read(path)
+                .map_err(|error| format!("could not read {}: {}", path.display(), error))?;
+            let mut payload = FilePayload::from_path(path, data)?;
+            if let Some(mime) = &args.mime {
+                payload.mime_type = mime.clone();
+            }
+            println!(
+                "storing {} ({}, {} bytes)",
+                payload.filename,
+                payload.mime_type,
+                payload.data.len()
+            );
+            Message::File(payload)
+        }
+        (None, Some(message)) => Message::Text(message.to_string()),
+        (None, None) => unreachable!("target() requires a message without --file"),
+    };
+    let message = message.to_payload(args.compress)?;
+
+    let mut png = read_png(&args.file)?;
+    match target.chunk_type {
+        Some(chunk_type) => encode_chunk(&mut png, chunk_type, &message, &args)?,
+        None => encode_lsb(&mut png, &message, &args)?,
+    }
+
+    let output = target.output.as_deref().unwrap_or(&args.file);
+    write_png(output, &png)
+}
+
+fn parse_recipients(args: &EncodeArgs) -> Result<Vec<PublicKey>> {
+    Ok(args
+        .recipients
+        .iter()
+        .map(|hex| recipients::public_key_from_hex(hex))
+        .collect::<std::result::Result<Vec<_>, _>>()?)
+}
+
+fn encode_chunk(png: &mut Png, chunk_type: &str, message: &[u8], args: &EncodeArgs) -> Result<()> {
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    if !chunk_type.is_valid() {
+        return Err(format!("{} has its reserved bit set", chunk_type).into());
+    }
+
+    let chunk = if let Some(password) = &args.password {
+        crypto::encrypt_chunk(chunk_type, message, password, &Kdf::default())?
+    } else if !args.recipients.is_empty() {
+        recipients::encrypt_chunk(chunk_type, message, &parse_recipients(args)?)?
+    } else {
+        Chunk::try_new(chunk_type, message.to_vec())?
+    };
+
+    let policy = if args.append { InsertionPolicy::BeforeIend } else { InsertionPolicy::Auto };
+    png.insert_chunk(chunk, policy);
+    Ok(())
+}
+
+fn encode_lsb(png: &mut Png, message: &[u8], args: &EncodeArgs) -> Result<()> {
+    if args.append {
+        return Err("--append only applies to chunk mode".into());
+    }
+    let payload = if let Some(password) = &args.password {
+        crypto::seal(message, password, &Kdf::default())?
+    } else if !args.recipients.is_empty() {
+        recipients::seal(message, &parse_recipients(args)?)?
+    } else {
+        message.to_vec()
+    };
+
+    let options = lsb_options(&args.lsb)?;
+    let capacity = stego::capacity(&png.decode_image()?, &options)?;
+    if payload.len() > capacity {
+        return Err(format!(
+            "the message takes {} bytes but the image only holds {}",
+            payload.len(),
+            capacity
+        )
+        .into());
+    }
+    stego::hide(png, &payload, &options)?;
+    println!("hid {} of {} bytes in the pixels", payload.len(), capacity);
+    Ok(())
+}
+
+fn lsb_options(args: &LsbArgs) -> Result<LsbOptions> {
+    let mut channel_mask = None;
+    for &channel in args.channels.iter() {
+        if channel > 3 {
+            return Err(format!("channel {} does not exist, pixels have at most 4", channel).into());
+        }
+        channel_mask = Some(channel_mask.unwrap_or(0) | 1 << channel);
+    }
+    let key = args.key.as_deref().map(LsbKey::from_passphrase).transpose()?;
+    Ok(LsbOptions {
+        bits_per_channel: args.bits,
+        channel_mask,
+        key,
+    })
+}
+
+pub fn decode(args: DecodeArgs) -> Result<()> {
+    let chunk_type = match (args.mode, &args.chunk_type) {
+        (Mode::Lsb, _) => {
+            let data = stego::reveal(&read_png(&args.file)?, &lsb_options(&args.lsb)?)?;
+            return decode_message(&data, "the hidden message", &args);
+        }
+        (Mode::Chunk, Some(chunk_type)) => chunk_type,
+        (Mode::Chunk, None) => return Err("chunk mode needs a chunk type".into()),
+    };
+
+    // Stream the file so only the chunks up to the message are ever read
+    let file = File::open(&args.file)
+        .map_err(|error| format!("could not read {}: {}", args.file.display(), error))?;
+    let chunks = ChunkReader::new(BufReader::new(file))
+        .map_err(|error| format!("{} is not a valid PNG: {}", args.file.display(), error))?;
+    let matching = matching_chunks(chunks, chunk_type, args.index);
+
+    let source = format!("the {} chunk", chunk_type);
+    let mut found = false;
+    for chunk in matching {
+        let chunk = chunk
+            .map_err(|error| format!("{} is not a valid PNG: {}", args.file.display(), error))?;
+        decode_message(chunk.data(), &source, &args)?;
+        found = true;
+        if !args.all {
+            break;
+        }
+    }
+
+    if !found {
+        return Err(format!("no {} chunk #{} in {}", chunk_type, args.index, args.file.display())
+            .into());
+    }
+    Ok(())
+}
+
+/// The chunks of `chunk_type` from the `index`-th on. Errors are passed
+/// through wherever they occur rather than counted as matches, so a corrupt
+/// file is reported as such whatever the index.
+fn matching_chunks<'a, I>(
+    chunks: I,
+    chunk_type: &'a str,
+    index: usize,
+) -> impl Iterator<Item = rust_png_cryptex::Result<Chunk>> + 'a
+where
+    I: Iterator<Item = rust_png_cryptex::Result<Chunk>> + 'a,
+{
+    let mut seen = 0;
+    chunks.filter(move |chunk| match chunk {
+        Ok(chunk) if chunk.chunk_type().to_string() == chunk_type => {
+            seen += 1;
+            seen > index
+        }
+        Ok(_) => false,
+        Err(_) => true,
+    })
+}
+
+/// Decrypts `data` as needed and reads the message header, then prints
+/// the message if it is text or restores it into --out-dir if it is a file. `source`
+/// names where the message came from, for error messages.
+fn decode_message(data: &[u8], source: &str, args: &DecodeArgs) -> Result<()> {
+    let data = if let Some(password) = &args.password {
+        crypto::open(data, password)?
+    } else if let Some(identity) = &args.identity {
+        recipients::open(data, &read_identity(identity)?)?
+    } else if crypto::is_encrypted(data) {
+        return Err(format!("{} is encrypted, pass --password", source).into());
+    } else if recipients::is_encrypted(data) {
+        return Err(format!("{} is encrypted, pass --identity", source).into());
+    } else {
+        data.to_vec()
+    };
+    match Message::from_payload(&data, args.max_size)
+        .map_err(|error| format!("{} does not hold a message: {}", source, error))?
+    {
+        Message::Text(message) => println!("{}", message),
+        Message::File(payload) => restore_file(&payload, source, args)?,
+    }
+    Ok(())
+}
+
+/// Writes a hidden file into --out-dir, refusing to overwrite anything
+fn restore_file(payload: &FilePayload, source: &str, args: &DecodeArgs) -> Result<()> {
+    let out_dir = args.out_dir.as_deref().ok_or_else(|| {
+        format!("{} holds the file {}, pass --out-dir", source, payload.filename)
+    })?;
+    let path = out_dir.join(&payload.filename);
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(&payload.data))
+        .map_err(|error| format!("could not write {}: {}", path.display(), error))?;
+    println!(
+        "restored {} ({}, {} bytes)",
+        path.display(),
+        payload.mime_type,
+        payload.data.len()
+    );
+    Ok(())
+}
+
+pub fn remove(args: RemoveArgs) -> Result<()> {
+    let mut png = read_png(&args.file)?;
+    let removed = if args.all {
+        png.remove_all(&args.chunk_type)
+    } else {
+        let index = png.index_of(&args.chunk_type, args.index).ok_or_else(|| {
+            format!("no {} chunk #{} in {}", args.chunk_type, args.index, args.file.display())
+        })?;
+        vec![png.remove_at(index)?]
+    };
+    if removed.is_empty() {
+        return Err(format!("no {} chunk in {}", args.chunk_type, args.file.display()).into());
+    }
+
+    write_png(&args.file, &png)?;
+    for chunk in removed {
+        println!("removed {}", chunk);
+    }
+    Ok(())
+}
+
+pub fn print(args: PrintArgs) -> Result<()> {
+    let png = read_png(&args.file)?;
+    match png.ihdr() {
+        Ok(ihdr) => println!("{}: {}", args.file.display(), ihdr),
+        Err(error) => println!("{}: {}", args.file.display(), error),
+    }
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        let chunk_type = chunk.chunk_type().to_string();
+        let occurrence = png.chunks()[..index]
+            .iter()
+            .filter(|earlier| earlier.chunk_type() == chunk.chunk_type())
+            .count();
+        println!(
+            "{:>4}  {}[{}]  {} bytes, crc {:#010x}",
+            index,
+            chunk_type,
+            occurrence,
+            chunk.length(),
+            chunk.crc()
+        );
+    }
+    if !png.trailer().is_empty() {
+        println!("      {} bytes after IEND", png.trailer().len());
+    }
+    Ok(())
+}
+
+pub fn keygen(args: KeygenArgs) -> Result<()> {
+    let (secret, public) = if args.signing {
+        let key = signature::generate_signing_key();
+        (key.to_bytes(), key.verifying_key().to_bytes())
+    } else {
+        let (secret, public) = recipients::generate_identity();
+        (secret.to_bytes(), public.to_bytes())
+    };
+    println!("# public key: {}", recipients::key_to_hex(&public));
+    println!("{}", recipients::key_to_hex(&secret));
+    Ok(())
+}
+
+pub fn sign(args: SignArgs) -> Result<()> {
+    let key = signature::signing_key_from_hex(&read_key_file(&args.key)?)?;
+    let mut png = read_png(&args.file)?;
+
+    let message_types: Vec<&str> = args.message_types.iter().map(String::as_str).collect();
+    let covered = signature::default_coverage(&png, &message_types);
+    signature::sign(&mut png, &key, &covered)?;
+
+    let output = args.output.as_deref().unwrap_or(&args.file);
+    write_png(output, &png)?;
+    for name in covered {
+        println!("signed {}", name);
+    }
+    Ok(())
+}
+
+pub fn verify(args: VerifyArgs) -> Result<()> {
+    let trusted = signature::verifying_key_from_hex(&args.signer)?;
+    let png = read_png(&args.file)?;
+    let verification = signature::verify(&png, &trusted)?;
+
+    let signer = recipients::key_to_hex(verification.signer.as_bytes());
+    println!("valid signature by {}", signer);
+    for name in verification.covered.iter() {
+        println!("  covered      {}", name);
+    }
+    for name in verification.uncovered.iter() {
+        println!("  not covered  {}", name);
+    }
+    Ok(())
+}
+
+pub fn text(args: TextArgs) -> Result<()> {
+    match args.action {
+        TextAction::List { file } => {
+            for entry in read_png(&file)?.text_entries()? {
+                println!("{}", entry);
+            }
+        }
+        TextAction::Get { file, keyword } => {
+            let entry = read_png(&file)?
+                .text(&keyword)?
+                .ok_or_else(|| format!("no {} text entry in {}", keyword, file.display()))?;
+            println!("{}", entry.text);
+        }
+        TextAction::Set(args) => set_text(args)?,
+        TextAction::Delete { file, keyword } => {
+            let mut png = read_png(&file)?;
+            let removed = png.remove_text(&keyword);
+            if removed.is_empty() {
+                return Err(format!("no {} text entry in {}", keyword, file.display()).into());
+            }
+            write_png(&file, &png)?;
+            for chunk in removed {
+                println!("removed {}", chunk);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn set_text(args: TextSetArgs) -> Result<()> {
+    let mut entry = TextEntry::new(&args.keyword, &args.value, args.compress);
+    if let Some(language) = args.language {
+        entry.kind = TextKind::Itxt {
+            compressed: args.compress,
+            language_tag: language,
+            translated_keyword: String::new(),
+        };
+    }
+
+    let mut png = read_png(&args.file)?;
+    png.set_text(&entry)?;
+    let output = args.output.as_deref().unwrap_or(&args.file);
+    write_png(output, &png)
+}
+
+pub fn exif(args: ExifArgs) -> Result<()> {
+    match args.action {
+        ExifAction::List { file } => {
+            let exif = read_png(&file)?
+                .exif()?
+                .ok_or_else(|| format!("no eXIf chunk in {}", file.display()))?;
+            for (ifd, entry) in exif.entries() {
+                // GPS tags are numbered separately, so the names do not apply
+                let name = match ifd.as_str() {
+                    "GPS" => None,
+                    _ => exif::tag_name(entry.tag),
+                };
+                println!(
+                    "{:<7} {:#06x} {:<28} {}",
+                    ifd,
+                    entry.tag,
+                    name.unwrap_or(""),
+                    entry.value
+                );
+            }
+        }
+        ExifAction::Delete { file, tags, output } => {
+            let mut png = read_png(&file)?;
+            let mut exif = png
+                .exif()?
+                .ok_or_else(|| format!("no eXIf chunk in {}", file.display()))?;
+            for name in tags.iter() {
+                let tag = exif::tag_from_name(name)
+                    .ok_or_else(|| format!("{} is not a known EXIF tag", name))?;
+                println!("removed {} {}", exif.remove_tag(tag), name);
+            }
+            png.set_exif(&exif);
+            write_png(output.as_deref().unwrap_or(&file), &png)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn scrub(args: ScrubArgs) -> Result<()> {
+    let parse_types = |types: &[String]| {
+        types
+            .iter()
+            .map(|chunk_type| ChunkType::from_str(chunk_type))
+            .collect::<std::result::Result<Vec<_>, _>>()
+    };
+    let policy = ScrubPolicy {
+        keep_color_management: !args.strip_color,
+        allow: parse_types(&args.keep)?,
+        deny: parse_types(&args.remove)?,
+    };
+
+    let mut png = read_png(&args.file)?;
+    let report = png.scrub(&policy);
+    let verb = if args.dry_run { "would remove" } else { "removed" };
+    for chunk in report.removed.iter() {
+        println!("{} {}", verb, chunk);
+    }
+    println!("{} {} chunks, {} bytes", verb, report.removed.len(), report.removed_bytes());
+
+    if !args.dry_run {
+        write_png(args.output.as_deref().unwrap_or(&args.file), &png)?;
+    }
+    Ok(())
+}
+
+pub fn analyze(args: AnalyzeArgs) -> Result<()> {
+    // Work on the raw bytes, since data after IEND is one of the things to find
+    let bytes = fs::read(&args.file)
+        .map_err(|error| format!("could not read {}: {}", args.file.display(), error))?;
+    let options = AnalysisOptions { max_ancillary_length: args.max_chunk_size };
+    let report = analysis::analyze(&bytes, &options)
+        .map_err(|error| format!("{} is not a valid PNG: {}", args.file.display(), error))?;
+    if args.json {
+        println!("{}", report.to_json());
+    } else {
+        print!("{}: {}", args.file.display(), report);
+    }
+    Ok(())
+}
+
+pub fn trailer(args: TrailerArgs) -> Result<()> {
+    match args.action {
+        TrailerAction::Show { file, all } => {
+            let png = read_png(&file)?;
+            let trailer = png.trailer();
+            println!("{}: {} bytes after IEND", file.display(), trailer.len());
+            let shown = if all { trailer.len() } else { trailer.len().min(256) };
+            print_hex_dump(&trailer[..shown]);
+            if shown < trailer.len() {
+                println!("... {} more bytes", trailer.len() - shown);
+            }
+        }
+        TrailerAction::Extract { file, output } => {
+            let png = read_png(&file)?;
+            if png.trailer().is_empty() {
+                return Err(format!("{} has nothing after IEND", file.display()).into());
+            }
+            fs::write(&output, png.trailer())
+                .map_err(|error| format!("could not write {}: {}", output.display(), error))?;
+            println!("extracted {} bytes", png.trailer().len());
+        }
+        TrailerAction::Strip { file, output } => {
+            let mut png = read_png(&file)?;
+            let removed = png.take_trailer();
+            write_png(output.as_deref().unwrap_or(&file), &png)?;
+            println!("removed {} bytes", removed.len());
+        }
+        TrailerAction::Write { file, input, output } => {
+            let trailer = fs::read(&input)
+                .map_err(|error| format!("could not read {}: {}", input.display(), error))?;
+            let mut png = read_png(&file)?;
+            println!("wrote {} bytes after IEND", trailer.len());
+            png.set_trailer(trailer);
+            write_png(output.as_deref().unwrap_or(&file), &png)?;
+        }
+    }
+    Ok(())
+}
+
+/// Prints 16 bytes per line as offset, hex and printable ASCII
+fn print_hex_dump(bytes: &[u8]) {
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+            .collect();
+        println!("{:08x}  {:<47}  {}", line * 16, hex.join(" "), ascii);
+    }
+}
+
+/// Reads the secret key from a key file written by `keygen`, skipping `#`
+/// comment lines
+fn read_key_file(path: &Path) -> Result<String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|error| format!("could not read {}: {}", path.display(), error))?;
+    let line = contents
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .ok_or_else(|| format!("{} does not contain a secret key", path.display()))?;
+    Ok(line.to_string())
+}
+
+fn read_identity(path: &Path) -> Result<StaticSecret> {
+    Ok(recipients::secret_key_from_hex(&read_key_file(path)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn file(chunks: &[Chunk]) -> Vec<u8> {
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        for chunk in chunks {
+            bytes.extend(chunk.as_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_matching_chunks() {
+        let bytes = file(&[chunk("ruSt", b"first"), chunk("miDl", b""), chunk("ruSt", b"second")]);
+        let chunks = ChunkReader::new(bytes.as_slice()).unwrap();
+        let data: Vec<Vec<u8>> = matching_chunks(chunks, "ruSt", 1)
+            .map(|chunk| chunk.unwrap().data().to_vec())
+            .collect();
+        assert_eq!(data, [b"second".to_vec()]);
+    }
+
+    #[test]
+    fn test_matching_chunks_reports_errors_at_any_index() {
+        let mut bytes = file(&[chunk("ruSt", b"first"), chunk("ruSt", b"second")]);
+        // Corrupt the CRC of the first chunk
+        bytes[8 + 12 + 4] ^= 1;
+        for index in [0, 1] {
+            let chunks = ChunkReader::new(bytes.as_slice()).unwrap();
+            let first = matching_chunks(chunks, "ruSt", index).next().unwrap();
+            assert!(matches!(first, Err(rust_png_cryptex::Error::CrcMismatch { .. })));
+        }
+    }
+
+    #[test]
+    fn test_default_signature_covers_message_chunk() {
+        let dir = std::env::temp_dir().join(format!("pngme-sign-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (file_path, key_path) = (dir.join("signed.png"), dir.join("signing.key"));
+        let chunks = [chunk("IHDR", b"header"), chunk("ruSt", b"message"), chunk("IEND", b"")];
+        fs::write(&file_path, file(&chunks)).unwrap();
+        let key = signature::generate_signing_key();
+        fs::write(&key_path, recipients::key_to_hex(&key.to_bytes())).unwrap();
+        let signer = recipients::key_to_hex(key.verifying_key().as_bytes());
+        let verify_args = || VerifyArgs { file: file_path.clone(), signer: signer.clone() };
+
+        // No --message-type: the private ruSt chunk is signed anyway
+        let sign_args = SignArgs {
+            file: file_path.clone(),
+            key: key_path.clone(),
+            message_types: Vec::new(),
+            output: None,
+        };
+        sign(sign_args).unwrap();
+        assert!(verify(verify_args()).is_ok());
+
+        let mut png = read_png(&file_path).unwrap();
+        png.remove_chunk("ruSt").unwrap();
+        png.insert_chunk(chunk("ruSt", b"forged"), InsertionPolicy::Auto);
+        write_png(&file_path, &png).unwrap();
+        let result = verify(verify_args());
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,69 @@
+use crate::error::{Error, Result};
+use crate::zlib;
+
+/// zlib-wrapped deflate, the same as PNG's own image data. The message
+/// header records the algorithm, see `payload`.
+pub const DEFLATE: u8 = 1;
+
+/// Default cap on the size of a decompressed message
+pub const DEFAULT_LIMIT: usize = 16 << 20;
+
+/// Deflates `message`. Compress before encrypting; ciphertext does not shrink.
+pub fn compress(message: &[u8]) -> Vec<u8> {
+    zlib::deflate_with_level(message, 9)
+}
+
+/// Reverses `compress` for a message of `length` bytes. Lengths over
+/// `limit` are refused before inflating, and inflating stops at `length`,
+/// so a small hostile stream cannot expand without bound.
+pub fn decompress(data: &[u8], length: usize, limit: usize) -> Result<Vec<u8>> {
+    if length > limit {
+        return Err(Error::InvalidPayload { reason: "decompressed message would be too large" });
+    }
+    let message = zlib::inflate(data, length).map_err(|reason| Error::InvalidPayload { reason })?;
+    if message.len() != length {
+        return Err(Error::InvalidPayload { reason: "decompressed length does not match" });
+    }
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let message = b"a long message, a long message, a long message".repeat(20);
+        let compressed = compress(&message);
+        assert!(compressed.len() < message.len() / 4);
+        assert_eq!(decompress(&compressed, message.len(), DEFAULT_LIMIT).unwrap(), message);
+        assert_eq!(decompress(&compress(b""), 0, 0).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_limit() {
+        let compressed = compress(&[0; 10_000]);
+        assert!(decompress(&compressed, 10_000, 10_000).is_ok());
+        assert_eq!(
+            decompress(&compressed, 10_000, 9_999),
+            Err(Error::InvalidPayload { reason: "decompressed message would be too large" })
+        );
+    }
+
+    #[test]
+    fn test_wrong_length() {
+        // A bomb that claims to be small stops inflating at the claimed length
+        let compressed = compress(&[0; 1 << 20]);
+        assert!(decompress(&compressed, 100, DEFAULT_LIMIT).is_err());
+        assert!(decompress(&compress(b"short"), 6, DEFAULT_LIMIT).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_stream() {
+        let mut compressed = compress(b"message");
+        let last = compressed.len() - 1;
+        compressed[last] ^= 0xff;
+        assert!(decompress(&compressed, 7, DEFAULT_LIMIT).is_err());
+        assert!(decompress(b"plain text message", 7, DEFAULT_LIMIT).is_err());
+    }
+}
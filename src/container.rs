@@ -0,0 +1,217 @@
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, Result};
+
+const CHECKSUM_LENGTH: usize = 32;
+
+/// Fallback for files whose type is not known
+pub const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// File extensions and the MIME types `mime_type_for` gives them
+const MIME_TYPES: [(&str, &str); 17] = [
+    ("txt", "text/plain"),
+    ("html", "text/html"),
+    ("csv", "text/csv"),
+    ("json", "application/json"),
+    ("xml", "application/xml"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("7z", "application/x-7z-compressed"),
+    ("pem", "application/x-pem-file"),
+    ("asc", "application/pgp-keys"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("mp3", "audio/mpeg"),
+];
+
+/// A file hidden as a message: its bytes along with the name and type
+/// needed to restore it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePayload {
+    /// Bare file name, without any directory
+    pub filename: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Guesses a MIME type from the file extension
+pub fn mime_type_for(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    MIME_TYPES
+        .iter()
+        .find(|(known, _)| *known == extension)
+        .map_or(DEFAULT_MIME_TYPE, |(_, mime_type)| mime_type)
+}
+
+/// A file name is only restored if it cannot point outside the directory
+/// it is written to
+fn check_filename(filename: &str) -> Result<()> {
+    let unsafe_name = filename.is_empty()
+        || filename == "."
+        || filename == ".."
+        || filename.contains(['/', '\\', '\0'])
+        || filename.len() > u8::MAX as usize;
+    if unsafe_name {
+        return Err(Error::InvalidPayload { reason: "file name is empty, too long or a path" });
+    }
+    Ok(())
+}
+
+fn invalid(reason: &'static str) -> Error {
+    Error::InvalidPayload { reason }
+}
+
+impl FilePayload {
+    /// Wraps `data` under the file name of `path`, guessing the MIME type
+    /// from its extension
+    pub fn from_path(path: &Path, data: Vec<u8>) -> Result<FilePayload> {
+        let filename = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or(invalid("file name is not valid UTF-8"))?;
+        check_filename(filename)?;
+        Ok(FilePayload {
+            filename: filename.to_string(),
+            mime_type: mime_type_for(path).to_string(),
+            data,
+        })
+    }
+
+    /// Lays the payload out as
+    /// `name length | name | type length | type | size | sha256 | data`,
+    /// with one-byte lengths, a 64-bit big-endian size and a SHA-256 of the
+    /// data. It is versioned by the message header it travels in, see `payload`.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        check_filename(&self.filename)?;
+        if self.mime_type.len() > u8::MAX as usize {
+            return Err(invalid("MIME type is too long"));
+        }
+        let mut bytes = Vec::with_capacity(80 + self.data.len());
+        bytes.push(self.filename.len() as u8);
+        bytes.extend_from_slice(self.filename.as_bytes());
+        bytes.push(self.mime_type.len() as u8);
+        bytes.extend_from_slice(self.mime_type.as_bytes());
+        bytes.extend_from_slice(&(self.data.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&Sha256::digest(&self.data));
+        bytes.extend_from_slice(&self.data);
+        Ok(bytes)
+    }
+}
+
+impl TryFrom<&[u8]> for FilePayload {
+    type Error = Error;
+
+    /// Parses a payload, checking its size and checksum against the data
+    fn try_from(bytes: &[u8]) -> Result<FilePayload> {
+        let mut rest = bytes;
+        let mut take = |length: usize| -> Result<&[u8]> {
+            if rest.len() < length {
+                return Err(invalid("file payload is truncated"));
+            }
+            let (field, after) = rest.split_at(length);
+            rest = after;
+            Ok(field)
+        };
+
+        let name_length = take(1)?[0] as usize;
+        let filename = std::str::from_utf8(take(name_length)?)
+            .map_err(|_| invalid("file name is not valid UTF-8"))?
+            .to_string();
+        check_filename(&filename)?;
+        let mime_length = take(1)?[0] as usize;
+        let mime_type = std::str::from_utf8(take(mime_length)?)
+            .map_err(|_| invalid("MIME type is not valid UTF-8"))?
+            .to_string();
+        let size = u64::from_be_bytes(take(8)?.try_into().unwrap());
+        let checksum = take(CHECKSUM_LENGTH)?;
+        let data = take(usize::try_from(size).map_err(|_| invalid("file is too large"))?)?;
+        if !rest.is_empty() {
+            return Err(invalid("file payload is longer than its recorded size"));
+        }
+        if Sha256::digest(data).as_slice() != checksum {
+            return Err(invalid("file checksum does not match"));
+        }
+
+        Ok(FilePayload { filename, mime_type, data: data.to_vec() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> FilePayload {
+        FilePayload::from_path(Path::new("/home/user/Report.PDF"), b"%PDF-1.7\x00\xff".to_vec())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_from_path() {
+        let payload = payload();
+        assert_eq!(payload.filename, "Report.PDF");
+        assert_eq!(payload.mime_type, "application/pdf");
+        assert_eq!(mime_type_for(Path::new("key")), DEFAULT_MIME_TYPE);
+        assert_eq!(mime_type_for(Path::new("archive.tar.gz")), "application/gzip");
+        assert!(FilePayload::from_path(Path::new("/"), Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let payload = payload();
+        let bytes = payload.to_bytes().unwrap();
+        assert_eq!(FilePayload::try_from(bytes.as_slice()).unwrap(), payload);
+
+        let empty = FilePayload { data: Vec::new(), ..payload };
+        let bytes = empty.to_bytes().unwrap();
+        assert_eq!(FilePayload::try_from(bytes.as_slice()).unwrap(), empty);
+    }
+
+    #[test]
+    fn test_damaged_payloads() {
+        let bytes = payload().to_bytes().unwrap();
+        for length in 0..bytes.len() {
+            assert!(FilePayload::try_from(&bytes[..length]).is_err(), "{}", length);
+        }
+
+        let mut flipped = bytes.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            FilePayload::try_from(flipped.as_slice()),
+            Err(Error::InvalidPayload { reason: "file checksum does not match" })
+        );
+
+        let mut longer = bytes;
+        longer.push(0);
+        assert!(FilePayload::try_from(longer.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_unsafe_filenames() {
+        for filename in ["", ".", "..", "../evil", "dir/file", "C:\\file", "nul\0"] {
+            let named = FilePayload {
+                filename: filename.to_string(),
+                mime_type: String::from(DEFAULT_MIME_TYPE),
+                data: Vec::new(),
+            };
+            assert!(named.to_bytes().is_err(), "{:?}", filename);
+
+            // Hand-built payloads with such names are rejected when parsed
+            let mut bytes = payload().to_bytes().unwrap();
+            let start = 1;
+            let end = start + bytes[start - 1] as usize;
+            bytes.splice(start..end, filename.bytes());
+            bytes[start - 1] = filename.len() as u8;
+            assert!(FilePayload::try_from(bytes.as_slice()).is_err(), "{:?}", filename);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{Error, Result};
+
+/// Chunk type used for passphrase-encrypted messages: ancillary, private,
+/// safe to copy
+pub const ENCRYPTED_CHUNK_TYPE: [u8; 4] = *b"crYp";
+
+/// Marks the start of every encrypted envelope
+const MAGIC: [u8; 4] = *b"cptx";
+const VERSION: u8 = 1;
+/// Argon2id key derivation followed by ChaCha20-Poly1305
+const ARGON2ID_CHACHA20POLY1305: u8 = 1;
+
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+const HEADER_LENGTH: usize = MAGIC.len() + 2 + 12 + SALT_LENGTH + NONCE_LENGTH;
+
+/// Largest cost parameters accepted from an envelope header. Argon2 itself
+/// takes any value, so these bound the time and memory spent on decoding.
+const MAX_MEMORY_KIB: u32 = 1 << 18;
+const MAX_ITERATIONS: u32 = 10;
+const MAX_PARALLELISM: u32 = 16;
+
+/// Argon2id cost parameters. They are stored in the envelope header, so
+/// decryption always uses whatever the message was sealed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Kdf {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for Kdf {
+    fn default() -> Kdf {
+        Kdf {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl Kdf {
+    /// Rejects costs above the fixed maximums before any work is done
+    fn check(&self) -> Result<()> {
+        let reason = if self.memory_kib > MAX_MEMORY_KIB {
+            "KDF memory cost is too large"
+        } else if self.iterations > MAX_ITERATIONS {
+            "KDF iteration count is too large"
+        } else if self.parallelism > MAX_PARALLELISM {
+            "KDF parallelism is too large"
+        } else {
+            return Ok(());
+        };
+        Err(Error::InvalidPayload { reason })
+    }
+
+    pub(crate) fn derive_key(&self, passphrase: &str, salt: &[u8]) -> Result<[u8; 32]> {
+        self.check()?;
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, Some(32))
+            .map_err(|_| Error::InvalidPayload { reason: "invalid KDF parameters" })?;
+
+        let mut key = [0; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|_| Error::InvalidPayload { reason: "invalid KDF parameters" })?;
+        Ok(key)
+    }
+}
+
+/// Returns true if `data` starts like an envelope produced by `seal`
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Encrypts and authenticates `message` under a key stretched from `passphrase`.
+///
+/// The envelope is laid out as
+/// `magic | version | algorithm | memory | iterations | parallelism | salt | nonce | ciphertext`,
+/// with the whole header bound to the ciphertext as associated data.
+pub fn seal(message: &[u8], passphrase: &str, kdf: &Kdf) -> Result<Vec<u8>> {
+    let mut salt = [0; SALT_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let key = kdf.derive_key(passphrase, &salt)?;
+
+    let mut envelope = Vec::with_capacity(HEADER_LENGTH + message.len() + 16);
+    envelope.extend_from_slice(&MAGIC);
+    envelope.push(VERSION);
+    envelope.push(ARGON2ID_CHACHA20POLY1305);
+    envelope.extend_from_slice(&kdf.memory_kib.to_be_bytes());
+    envelope.extend_from_slice(&kdf.iterations.to_be_bytes());
+    envelope.extend_from_slice(&kdf.parallelism.to_be_bytes());
+    envelope.extend_from_slice(&salt);
+    envelope.extend_from_slice(&nonce);
+
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: message, aad: &envelope })
+        .map_err(|_| Error::InvalidPayload { reason: "message is too long to encrypt" })?;
+    envelope.extend_from_slice(&ciphertext);
+    Ok(envelope)
+}
+
+/// Reverses `seal`, failing with `DecryptionFailed` on a wrong passphrase
+/// or any modification of the envelope
+pub fn open(envelope: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    if envelope.len() < HEADER_LENGTH {
+        return Err(Error::InvalidPayload { reason: "encrypted message is too short" });
+    }
+    let (header, ciphertext) = envelope.split_at(HEADER_LENGTH);
+    if !is_encrypted(header) {
+        return Err(Error::InvalidPayload { reason: "message is not encrypted" });
+    }
+    if header[4] != VERSION {
+        return Err(Error::UnsupportedVersion { version: header[4] });
+    }
+    if header[5] != ARGON2ID_CHACHA20POLY1305 {
+        return Err(Error::UnsupportedAlgorithm { algorithm: header[5] });
+    }
+
+    let read_u32 = |at: usize| u32::from_be_bytes(header[at..at + 4].try_into().unwrap());
+    let kdf = Kdf {
+        memory_kib: read_u32(6),
+        iterations: read_u32(10),
+        parallelism: read_u32(14),
+    };
+    let salt = &header[18..18 + SALT_LENGTH];
+    let nonce = Nonce::from_slice(&header[18 + SALT_LENGTH..]);
+    let key = kdf.derive_key(passphrase, salt)?;
+
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    cipher
+        .decrypt(nonce, Payload { msg: ciphertext, aad: header })
+        .map_err(|_| Error::DecryptionFailed)
+}
+
+/// Seals `message` into a new chunk, whose type has to be ancillary and private
+pub fn encrypt_chunk(
+    chunk_type: ChunkType,
+    message: &[u8],
+    passphrase: &str,
+    kdf: &Kdf,
+) -> Result<Chunk> {
+    check_message_chunk_type(&chunk_type)?;
+    Chunk::try_new(chunk_type, seal(message, passphrase, kdf)?)
+}
+
+pub fn decrypt_chunk(chunk: &Chunk, passphrase: &str) -> Result<Vec<u8>> {
+    open(chunk.data(), passphrase)
+}
+
+/// Encrypted messages go in ancillary, private chunks so that decoders
+/// which do not know the type simply skip it
+pub(crate) fn check_message_chunk_type(chunk_type: &ChunkType) -> Result<()> {
+    let misplaced_bit = if chunk_type.is_critical() {
+        Some(0)
+    } else if chunk_type.is_public() {
+        Some(1)
+    } else {
+        None
+    };
+    match misplaced_bit {
+        Some(offset) => Err(Error::InvalidChunkType {
+            chunk_type: chunk_type.to_string(),
+            offset,
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // Keep the tests fast, the default cost is tuned for real passphrases
+    const CHEAP: Kdf = Kdf {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    fn test_seal_and_open() {
+        let envelope = seal(b"meet me at noon", "hunter2", &CHEAP).unwrap();
+        assert!(is_encrypted(&envelope));
+        assert_eq!(open(&envelope, "hunter2").unwrap(), b"meet me at noon");
+    }
+
+    #[test]
+    fn test_wrong_passphrase() {
+        let envelope = seal(b"meet me at noon", "hunter2", &CHEAP).unwrap();
+        assert_eq!(open(&envelope, "hunter3").unwrap_err(), Error::DecryptionFailed);
+    }
+
+    #[test]
+    fn test_tampered_header_is_rejected() {
+        let mut envelope = seal(b"meet me at noon", "hunter2", &CHEAP).unwrap();
+        // Flip a bit of the salt, which is authenticated as associated data
+        envelope[20] ^= 1;
+        assert_eq!(open(&envelope, "hunter2").unwrap_err(), Error::DecryptionFailed);
+    }
+
+    #[test]
+    fn test_unknown_version() {
+        let mut envelope = seal(b"meet me at noon", "hunter2", &CHEAP).unwrap();
+        envelope[4] = 9;
+        assert_eq!(
+            open(&envelope, "hunter2").unwrap_err(),
+            Error::UnsupportedVersion { version: 9 }
+        );
+    }
+
+    #[test]
+    fn test_hostile_costs() {
+        let envelope = seal(b"meet me at noon", "hunter2", &CHEAP).unwrap();
+        let cases = [
+            (6, MAX_MEMORY_KIB + 1, "KDF memory cost is too large"),
+            (10, MAX_ITERATIONS + 1, "KDF iteration count is too large"),
+            (14, MAX_PARALLELISM + 1, "KDF parallelism is too large"),
+        ];
+        for (offset, cost, reason) in cases {
+            // u32::MAX would run for hours if it reached Argon2
+            for cost in [cost, u32::MAX] {
+                let mut hostile = envelope.clone();
+                hostile[offset..offset + 4].copy_from_slice(&cost.to_be_bytes());
+                assert_eq!(open(&hostile, "hunter2"), Err(Error::InvalidPayload { reason }));
+            }
+        }
+    }
+
+    #[test]
+    fn test_default_costs_are_accepted() {
+        assert!(Kdf::default().check().is_ok());
+        let largest = Kdf {
+            memory_kib: MAX_MEMORY_KIB,
+            iterations: MAX_ITERATIONS,
+            parallelism: MAX_PARALLELISM,
+        };
+        assert!(largest.check().is_ok());
+    }
+
+    #[test]
+    fn test_short_envelope() {
+        assert!(matches!(open(b"cptx", "hunter2"), Err(Error::InvalidPayload { .. })));
+    }
+
+    #[test]
+    fn test_encrypt_chunk() {
+        let chunk_type = ChunkType::try_from(ENCRYPTED_CHUNK_TYPE).unwrap();
+        let chunk = encrypt_chunk(chunk_type, b"meet me at noon", "hunter2", &CHEAP).unwrap();
+
+        assert!(chunk.data_as_string().map_or(true, |text| !text.contains("noon")));
+        assert_eq!(decrypt_chunk(&chunk, "hunter2").unwrap(), b"meet me at noon");
+    }
+
+    #[test]
+    fn test_encrypt_chunk_requires_private_ancillary_type() {
+        let critical = ChunkType::from_str("RuSt").unwrap();
+        assert!(encrypt_chunk(critical, b"hi", "hunter2", &CHEAP).is_err());
+
+        let public = ChunkType::from_str("rUSt").unwrap();
+        assert!(encrypt_chunk(public, b"hi", "hunter2", &CHEAP).is_err());
+    }
+}
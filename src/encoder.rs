@@ -0,0 +1,251 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{Error, Result};
+use crate::ihdr::{Ihdr, Interlace};
+use crate::image::Image;
+use crate::png::Png;
+use crate::scanline::{self, FilterType, Pass};
+use crate::zlib;
+
+/// How the encoder picks the filter for each scanline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilterStrategy {
+    /// The same filter on every line
+    Fixed(FilterType),
+    /// Try every filter and keep the one whose output has the smallest sum
+    /// of absolute values, read as signed bytes. Images below 8 bits use no
+    /// filter, as the spec recommends.
+    #[default]
+    Adaptive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncoderOptions {
+    pub filter: FilterStrategy,
+    /// zlib level from 0 (store only) to 9 (smallest output)
+    pub compression_level: u32,
+    /// Largest IDAT payload; the compressed stream is split across as many
+    /// IDAT chunks as needed
+    pub idat_size: usize,
+    pub interlace: Interlace,
+}
+
+impl Default for EncoderOptions {
+    fn default() -> EncoderOptions {
+        EncoderOptions {
+            filter: FilterStrategy::Adaptive,
+            compression_level: 6,
+            idat_size: 8192,
+            interlace: Interlace::None,
+        }
+    }
+}
+
+/// The filter byte followed by the filtered line, for the chosen strategy
+fn filter_line(strategy: FilterStrategy, line: &[u8], previous: &[u8], ihdr: &Ihdr) -> Vec<u8> {
+    let bpp = ihdr.bits_per_pixel().div_ceil(8);
+    let candidates: &[FilterType] = match strategy {
+        FilterStrategy::Fixed(filter) => &[filter],
+        FilterStrategy::Adaptive if ihdr.bit_depth < 8 => &[FilterType::None],
+        FilterStrategy::Adaptive => &FilterType::ALL,
+    };
+
+    candidates
+        .iter()
+        .map(|&filter| {
+            let mut filtered = vec![filter.to_byte()];
+            scanline::filter(filter, line, previous, bpp, &mut filtered);
+            filtered
+        })
+        .min_by_key(|filtered| {
+            filtered[1..].iter().map(|&byte| (byte as i8).unsigned_abs() as u32).sum::<u32>()
+        })
+        .expect("there is always at least one candidate filter")
+}
+
+/// Packs the pixels of one pass row back into PNG's row format
+fn pack_row(image: &Image, pass: &Pass, y: u32, out: &mut Vec<u8>) {
+    let pixel_bytes = image.pixel_bytes();
+    let mut bits = 0u16;
+    let mut filled = 0;
+    for x in 0..pass.width {
+        let (image_x, image_y) = pass.image_position(x, y);
+        let start = (image_y as usize * image.width as usize + image_x as usize) * pixel_bytes;
+        let pixel = &image.data[start..start + pixel_bytes];
+        if image.bit_depth >= 8 {
+            out.extend_from_slice(pixel);
+            continue;
+        }
+        for &sample in pixel {
+            bits = (bits << image.bit_depth) | sample as u16;
+            filled += image.bit_depth;
+            if filled == 8 {
+                out.push(bits as u8);
+                (bits, filled) = (0, 0);
+            }
+        }
+    }
+    if filled > 0 {
+        out.push((bits << (8 - filled)) as u8);
+    }
+}
+
+/// Builds a PNG holding just `image`: IHDR, the IDAT chunks and IEND
+pub fn encode(image: &Image, options: &EncoderOptions) -> Result<Png> {
+    let ihdr = Ihdr {
+        width: image.width,
+        height: image.height,
+        bit_depth: image.bit_depth,
+        color_type: image.layout.color_type(),
+        compression: 0,
+        filter: 0,
+        interlace: options.interlace,
+    };
+    // Round trip through the parser to apply every IHDR rule
+    let ihdr = Ihdr::try_from(&ihdr.to_chunk())?;
+    let expected = image.pixel_bytes() * image.width as usize * image.height as usize;
+    if image.data.len() != expected {
+        return Err(Error::InvalidPayload { reason: "pixel data does not match the image size" });
+    }
+    if image.bit_depth < 8 && image.data.iter().any(|&sample| sample >> image.bit_depth != 0) {
+        return Err(Error::InvalidPayload { reason: "sample does not fit the bit depth" });
+    }
+
+    let mut raw = Vec::new();
+    for pass in Pass::for_image(&ihdr) {
+        let mut previous = vec![0; ihdr.row_bytes(pass.width)];
+        for y in 0..pass.height {
+            let mut line = Vec::with_capacity(previous.len());
+            pack_row(image, &pass, y, &mut line);
+            raw.extend(filter_line(options.filter, &line, &previous, &ihdr));
+            previous = line;
+        }
+    }
+
+    let compressed = zlib::deflate_with_level(&raw, options.compression_level);
+    let idat_size = options.idat_size.clamp(1, Chunk::MAX_LENGTH as usize);
+    let mut chunks = vec![ihdr.to_chunk()];
+    for part in compressed.chunks(idat_size) {
+        chunks.push(Chunk::new(ChunkType::try_from(*b"IDAT")?, part.to_vec()));
+    }
+    chunks.push(Chunk::new(ChunkType::try_from(*b"IEND")?, Vec::new()));
+    Ok(Png::from_chunks(chunks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::ChannelLayout;
+
+    fn gradient(width: u32, height: u32, layout: ChannelLayout, bit_depth: u8) -> Image {
+        let mut image = Image::new(width, height, layout, bit_depth);
+        let max = if bit_depth == 16 { u16::MAX as u32 } else { (1 << bit_depth) - 1 };
+        for y in 0..height {
+            for x in 0..width {
+                for channel in 0..layout.channels() {
+                    let value = (x * 7 + y * 13 + channel as u32 * 29) % (max + 1);
+                    image.set_sample(x, y, channel, value as u16);
+                }
+            }
+        }
+        image
+    }
+
+    fn reference_decode(png: &Png) -> Vec<u8> {
+        let bytes = png.as_bytes();
+        let mut reader = ::png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).unwrap();
+        buffer.truncate(info.buffer_size());
+        buffer
+    }
+
+    #[test]
+    fn test_round_trip_every_layout() {
+        let layouts = [
+            (ChannelLayout::Gray, &[1, 2, 4, 8, 16][..]),
+            (ChannelLayout::GrayAlpha, &[8, 16]),
+            (ChannelLayout::Rgb, &[8, 16]),
+            (ChannelLayout::Rgba, &[8, 16]),
+        ];
+        for (layout, bit_depths) in layouts {
+            for &bit_depth in bit_depths {
+                for interlace in [Interlace::None, Interlace::Adam7] {
+                    let image = gradient(19, 11, layout, bit_depth);
+                    let options = EncoderOptions { interlace, ..Default::default() };
+                    let png = encode(&image, &options).unwrap();
+                    let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+                    assert_eq!(png.decode_image().unwrap(), image, "{:?} {}", layout, bit_depth);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_reference_decoder_agrees() {
+        let mut strategies = FilterType::ALL.map(FilterStrategy::Fixed).to_vec();
+        strategies.push(FilterStrategy::Adaptive);
+        for filter in strategies {
+            let image = gradient(23, 9, ChannelLayout::Rgba, 8);
+            let options = EncoderOptions { filter, ..Default::default() };
+            let png = encode(&image, &options).unwrap();
+            assert_eq!(reference_decode(&png), image.data, "{:?}", filter);
+        }
+
+        let image = gradient(23, 9, ChannelLayout::Rgb, 16);
+        let options = EncoderOptions { interlace: Interlace::Adam7, ..Default::default() };
+        let png = encode(&image, &options).unwrap();
+        assert_eq!(reference_decode(&png), image.data);
+    }
+
+    #[test]
+    fn test_fixed_filter_is_used() {
+        let image = gradient(8, 4, ChannelLayout::Rgb, 8);
+        let options = EncoderOptions {
+            filter: FilterStrategy::Fixed(FilterType::Paeth),
+            ..Default::default()
+        };
+        let png = encode(&image, &options).unwrap();
+        let data = png.image_data().unwrap();
+        assert!(data.scanlines().unwrap().iter().all(|line| line.filter == FilterType::Paeth));
+    }
+
+    #[test]
+    fn test_idat_size_and_level() {
+        let image = gradient(64, 64, ChannelLayout::Rgb, 8);
+        let options = EncoderOptions {
+            compression_level: 0,
+            idat_size: 1000,
+            ..Default::default()
+        };
+        let stored = encode(&image, &options).unwrap();
+        let idats: Vec<usize> =
+            stored.chunks_by_type("IDAT").map(|chunk| chunk.data().len()).collect();
+        assert!(idats.len() > 10);
+        assert!(idats.iter().all(|&length| length <= 1000));
+
+        let options = EncoderOptions { compression_level: 9, ..Default::default() };
+        let best = encode(&image, &options).unwrap();
+        assert!(best.as_bytes().len() < stored.as_bytes().len());
+        assert_eq!(best.decode_image().unwrap(), stored.decode_image().unwrap());
+    }
+
+    #[test]
+    fn test_invalid_images() {
+        let mut image = gradient(4, 4, ChannelLayout::Rgb, 8);
+        image.data.pop();
+        assert!(matches!(
+            encode(&image, &EncoderOptions::default()),
+            Err(Error::InvalidPayload { .. })
+        ));
+
+        let image = Image::new(4, 4, ChannelLayout::Rgb, 4);
+        assert!(encode(&image, &EncoderOptions::default()).is_err());
+
+        let mut image = Image::new(4, 4, ChannelLayout::Gray, 2);
+        image.data[0] = 4;
+        assert!(encode(&image, &EncoderOptions::default()).is_err());
+
+        assert!(encode(&Image::new(0, 4, ChannelLayout::Gray, 8), &Default::default()).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use std::{fmt::Display, io};
+
+/// Every way parsing or editing a PNG can fail.
+/// Byte offsets are relative to the start of the buffer handed to the parser,
+/// so errors coming out of `Png::try_from` point into the whole file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The first 8 bytes are not the PNG signature
+    InvalidSignature,
+    /// The field starting at `offset` needs `needed` bytes but the input ends first
+    Truncated { offset: usize, needed: usize },
+    /// The CRC stored after the chunk at `offset` does not match its contents
+    CrcMismatch {
+        chunk_type: String,
+        expected: u32,
+        actual: u32,
+        offset: usize,
+    },
+    /// The chunk type is not four ASCII letters or has its reserved bit set
+    InvalidChunkType { chunk_type: String, offset: usize },
+    /// The chunk length at `offset` exceeds the 2^31 - 1 limit set by the PNG spec
+    LengthTooLarge { length: u32, offset: usize },
+    /// The chunk cannot be placed here, e.g. anything before IHDR or after IEND
+    ChunkOrder { chunk_type: String, reason: &'static str },
+    /// A chunk's contents break the rules the PNG spec sets for its type
+    InvalidChunkData { chunk_type: String, reason: &'static str },
+    /// No chunk of the requested type exists
+    ChunkNotFound { chunk_type: String },
+    /// A chunk index is past the end of the chunk list
+    IndexOutOfBounds { index: usize, len: usize },
+    /// A message envelope is too short or otherwise not laid out as expected
+    InvalidPayload { reason: &'static str },
+    /// The envelope was written by a newer version of this crate
+    UnsupportedVersion { version: u8 },
+    /// The envelope names a cipher or KDF this crate does not implement
+    UnsupportedAlgorithm { algorithm: u8 },
+    /// A key could not be parsed
+    InvalidKey { reason: &'static str },
+    /// Authentication failed: the key or passphrase is wrong or the data was tampered with
+    DecryptionFailed,
+    /// A digital signature does not match the chunks it claims to cover
+    SignatureMismatch,
+    /// The signature is valid but was made by `signer`, given in hex, instead
+    /// of the trusted key
+    UntrustedSigner { signer: String },
+    /// The underlying reader or writer failed
+    Io { kind: io::ErrorKind, message: String },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Shifts every byte offset in the error by `base`, used when a parser
+    /// works on a sub-slice of a larger buffer
+    pub(crate) fn offset_by(self, base: usize) -> Error {
+        match self {
+            Error::Truncated { offset, needed } => Error::Truncated {
+                offset: offset + base,
+                needed,
+            },
+            Error::CrcMismatch {
+                chunk_type,
+                expected,
+                actual,
+                offset,
+            } => Error::CrcMismatch {
+                chunk_type,
+                expected,
+                actual,
+                offset: offset + base,
+            },
+            Error::InvalidChunkType { chunk_type, offset } => Error::InvalidChunkType {
+                chunk_type,
+                offset: offset + base,
+            },
+            Error::LengthTooLarge { length, offset } => Error::LengthTooLarge {
+                length,
+                offset: offset + base,
+            },
+            other => other,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidSignature => write!(f, "the PNG signature is invalid"),
+            Error::Truncated { offset, needed } => write!(
+                f,
+                "input ends early: {} bytes needed at offset {}",
+                needed, offset
+            ),
+            Error::CrcMismatch {
+                chunk_type,
+                expected,
+                actual,
+                offset,
+            } => write!(
+                f,
+                "CRC mismatch in {} chunk at offset {}: expected {:#010x}, found {:#010x}",
+                chunk_type, offset, expected, actual
+            ),
+            Error::InvalidChunkType { chunk_type, offset } => {
+                write!(f, "invalid chunk type {:?} at offset {}", chunk_type, offset)
+            }
+            Error::LengthTooLarge { length, offset } => write!(
+                f,
+                "chunk length {} at offset {} exceeds 2^31 - 1",
+                length, offset
+            ),
+            Error::ChunkOrder { chunk_type, reason } => {
+                write!(f, "{} chunk out of order: {}", chunk_type, reason)
+            }
+            Error::InvalidChunkData { chunk_type, reason } => {
+                write!(f, "invalid {} chunk: {}", chunk_type, reason)
+            }
+            Error::ChunkNotFound { chunk_type } => write!(f, "no {} chunk found", chunk_type),
+            Error::IndexOutOfBounds { index, len } => {
+                write!(f, "chunk index {} is out of bounds for {} chunks", index, len)
+            }
+            Error::InvalidPayload { reason } => write!(f, "invalid payload: {}", reason),
+            Error::UnsupportedVersion { version } => {
+                write!(f, "unsupported payload version {}", version)
+            }
+            Error::UnsupportedAlgorithm { algorithm } => {
+                write!(f, "unsupported algorithm id {}", algorithm)
+            }
+            Error::InvalidKey { reason } => write!(f, "invalid key: {}", reason),
+            Error::DecryptionFailed => write!(
+                f,
+                "decryption failed: wrong key or passphrase, or the data was modified"
+            ),
+            Error::SignatureMismatch => {
+                write!(f, "the signature does not match the signed chunks")
+            }
+            Error::UntrustedSigner { signer } => {
+                write!(f, "the signature is valid but was made by an untrusted key {}", signer)
+            }
+            Error::Io { message, .. } => write!(f, "I/O error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io {
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+}
@@ -0,0 +1,726 @@
+use std::fmt::Display;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{Error, Result};
+
+pub const EXIF_CHUNK_TYPE: [u8; 4] = *b"eXIf";
+
+pub const MAKE: u16 = 0x010F;
+pub const MODEL: u16 = 0x0110;
+pub const ARTIST: u16 = 0x013B;
+pub const COPYRIGHT: u16 = 0x8298;
+/// Points to the Exif sub-IFD
+pub const EXIF_IFD: u16 = 0x8769;
+/// Points to the GPS sub-IFD; deleting it removes all location data
+pub const GPS_IFD: u16 = 0x8825;
+pub const INTEROP_IFD: u16 = 0xA005;
+pub const MAKER_NOTE: u16 = 0x927C;
+pub const CAMERA_OWNER_NAME: u16 = 0xA430;
+pub const BODY_SERIAL_NUMBER: u16 = 0xA431;
+pub const LENS_SERIAL_NUMBER: u16 = 0xA435;
+/// Offset and length of a JPEG thumbnail, normally found in IFD1
+const THUMBNAIL_OFFSET: u16 = 0x0201;
+const THUMBNAIL_LENGTH: u16 = 0x0202;
+
+const TAG_NAMES: [(u16, &str); 18] = [
+    (MAKE, "Make"),
+    (MODEL, "Model"),
+    (0x0112, "Orientation"),
+    (0x0131, "Software"),
+    (0x0132, "DateTime"),
+    (ARTIST, "Artist"),
+    (COPYRIGHT, "Copyright"),
+    (EXIF_IFD, "ExifIFD"),
+    (GPS_IFD, "GPSInfo"),
+    (INTEROP_IFD, "InteropIFD"),
+    (0x9003, "DateTimeOriginal"),
+    (MAKER_NOTE, "MakerNote"),
+    (0x9286, "UserComment"),
+    (CAMERA_OWNER_NAME, "CameraOwnerName"),
+    (BODY_SERIAL_NUMBER, "BodySerialNumber"),
+    (LENS_SERIAL_NUMBER, "LensSerialNumber"),
+    (THUMBNAIL_OFFSET, "JPEGInterchangeFormat"),
+    (THUMBNAIL_LENGTH, "JPEGInterchangeFormatLength"),
+];
+
+/// Sub-IFDs nested deeper than this are rejected, which also stops cycles
+const MAX_DEPTH: usize = 4;
+/// IFD0, IFD1 and a few spares; real files have at most two
+const MAX_CHAINED_IFDS: usize = 8;
+
+/// Human readable name of a tag outside the GPS IFD, if it is a common one
+pub fn tag_name(tag: u16) -> Option<&'static str> {
+    TAG_NAMES.iter().find(|(known, _)| *known == tag).map(|(_, name)| *name)
+}
+
+/// Looks a tag up by name, or parses it as a number such as `0x8825`
+pub fn tag_from_name(name: &str) -> Option<u16> {
+    if let Some((tag, _)) = TAG_NAMES.iter().find(|(_, known)| known.eq_ignore_ascii_case(name)) {
+        return Some(*tag);
+    }
+    match name.strip_prefix("0x").or_else(|| name.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => name.parse().ok(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// `II`, as written by most cameras
+    LittleEndian,
+    /// `MM`
+    BigEndian,
+}
+
+impl ByteOrder {
+    fn u16(self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        match self {
+            ByteOrder::LittleEndian => u16::from_le_bytes(bytes),
+            ByteOrder::BigEndian => u16::from_be_bytes(bytes),
+        }
+    }
+
+    fn u32(self, bytes: &[u8]) -> u32 {
+        let bytes = bytes[..4].try_into().unwrap();
+        match self {
+            ByteOrder::LittleEndian => u32::from_le_bytes(bytes),
+            ByteOrder::BigEndian => u32::from_be_bytes(bytes),
+        }
+    }
+
+    fn u64(self, bytes: &[u8]) -> u64 {
+        let bytes = bytes[..8].try_into().unwrap();
+        match self {
+            ByteOrder::LittleEndian => u64::from_le_bytes(bytes),
+            ByteOrder::BigEndian => u64::from_be_bytes(bytes),
+        }
+    }
+
+    fn put_u16(self, out: &mut Vec<u8>, value: u16) {
+        out.extend_from_slice(&match self {
+            ByteOrder::LittleEndian => value.to_le_bytes(),
+            ByteOrder::BigEndian => value.to_be_bytes(),
+        });
+    }
+
+    fn put_u32(self, out: &mut Vec<u8>, value: u32) {
+        out.extend_from_slice(&match self {
+            ByteOrder::LittleEndian => value.to_le_bytes(),
+            ByteOrder::BigEndian => value.to_be_bytes(),
+        });
+    }
+
+    fn put_u64(self, out: &mut Vec<u8>, value: u64) {
+        out.extend_from_slice(&match self {
+            ByteOrder::LittleEndian => value.to_le_bytes(),
+            ByteOrder::BigEndian => value.to_be_bytes(),
+        });
+    }
+
+    fn write_u32_at(self, out: &mut [u8], at: usize, value: u32) {
+        let bytes = match self {
+            ByteOrder::LittleEndian => value.to_le_bytes(),
+            ByteOrder::BigEndian => value.to_be_bytes(),
+        };
+        out[at..at + 4].copy_from_slice(&bytes);
+    }
+}
+
+/// The value of one IFD entry, decoded according to its TIFF field type
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExifValue {
+    Byte(Vec<u8>),
+    /// Kept as raw bytes, including the terminating null, so rewriting is lossless
+    Ascii(Vec<u8>),
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Rational(Vec<(u32, u32)>),
+    SignedByte(Vec<i8>),
+    Undefined(Vec<u8>),
+    SignedShort(Vec<i16>),
+    SignedLong(Vec<i32>),
+    SignedRational(Vec<(i32, i32)>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+    /// A pointer tag such as `GPS_IFD`, resolved to the IFD it points to
+    SubIfd(Ifd),
+}
+
+impl ExifValue {
+    /// Bytes taken by one element of a TIFF field type, or `None` if unknown
+    fn element_size(field_type: u16) -> Option<usize> {
+        match field_type {
+            1 | 2 | 6 | 7 => Some(1),
+            3 | 8 => Some(2),
+            4 | 9 | 11 | 13 => Some(4),
+            5 | 10 | 12 => Some(8),
+            _ => None,
+        }
+    }
+
+    fn decode(field_type: u16, data: &[u8], order: ByteOrder) -> ExifValue {
+        let elements = |size: usize| data.chunks_exact(size);
+        match field_type {
+            1 => ExifValue::Byte(data.to_vec()),
+            2 => ExifValue::Ascii(data.to_vec()),
+            3 => ExifValue::Short(elements(2).map(|bytes| order.u16(bytes)).collect()),
+            4 | 13 => ExifValue::Long(elements(4).map(|bytes| order.u32(bytes)).collect()),
+            5 => ExifValue::Rational(
+                elements(8)
+                    .map(|bytes| (order.u32(bytes), order.u32(&bytes[4..])))
+                    .collect(),
+            ),
+            6 => ExifValue::SignedByte(data.iter().map(|&byte| byte as i8).collect()),
+            8 => ExifValue::SignedShort(elements(2).map(|bytes| order.u16(bytes) as i16).collect()),
+            9 => ExifValue::SignedLong(elements(4).map(|bytes| order.u32(bytes) as i32).collect()),
+            10 => ExifValue::SignedRational(
+                elements(8)
+                    .map(|bytes| (order.u32(bytes) as i32, order.u32(&bytes[4..]) as i32))
+                    .collect(),
+            ),
+            11 => ExifValue::Float(
+                elements(4)
+                    .map(|bytes| f32::from_bits(order.u32(bytes)))
+                    .collect(),
+            ),
+            12 => ExifValue::Double(
+                elements(8)
+                    .map(|bytes| f64::from_bits(order.u64(bytes)))
+                    .collect(),
+            ),
+            _ => ExifValue::Undefined(data.to_vec()),
+        }
+    }
+
+    /// TIFF field type number
+    pub fn field_type(&self) -> u16 {
+        match self {
+            ExifValue::Byte(_) => 1,
+            ExifValue::Ascii(_) => 2,
+            ExifValue::Short(_) => 3,
+            ExifValue::Long(_) | ExifValue::SubIfd(_) => 4,
+            ExifValue::Rational(_) => 5,
+            ExifValue::SignedByte(_) => 6,
+            ExifValue::Undefined(_) => 7,
+            ExifValue::SignedShort(_) => 8,
+            ExifValue::SignedLong(_) => 9,
+            ExifValue::SignedRational(_) => 10,
+            ExifValue::Float(_) => 11,
+            ExifValue::Double(_) => 12,
+        }
+    }
+
+    /// Number of elements, as stored in the entry's count field
+    pub fn count(&self) -> usize {
+        match self {
+            ExifValue::Byte(values) | ExifValue::Ascii(values) | ExifValue::Undefined(values) => {
+                values.len()
+            }
+            ExifValue::Short(values) => values.len(),
+            ExifValue::Long(values) => values.len(),
+            ExifValue::Rational(values) => values.len(),
+            ExifValue::SignedByte(values) => values.len(),
+            ExifValue::SignedShort(values) => values.len(),
+            ExifValue::SignedLong(values) => values.len(),
+            ExifValue::SignedRational(values) => values.len(),
+            ExifValue::Float(values) => values.len(),
+            ExifValue::Double(values) => values.len(),
+            ExifValue::SubIfd(_) => 1,
+        }
+    }
+
+    fn encode(&self, order: ByteOrder) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            ExifValue::Byte(values) | ExifValue::Ascii(values) | ExifValue::Undefined(values) => {
+                out.extend_from_slice(values)
+            }
+            ExifValue::Short(values) => {
+                values.iter().for_each(|&value| order.put_u16(&mut out, value))
+            }
+            ExifValue::Long(values) => {
+                values.iter().for_each(|&value| order.put_u32(&mut out, value))
+            }
+            ExifValue::Rational(values) => values.iter().for_each(|&(numerator, denominator)| {
+                order.put_u32(&mut out, numerator);
+                order.put_u32(&mut out, denominator);
+            }),
+            ExifValue::SignedByte(values) => out.extend(values.iter().map(|&value| value as u8)),
+            ExifValue::SignedShort(values) => {
+                values.iter().for_each(|&value| order.put_u16(&mut out, value as u16))
+            }
+            ExifValue::SignedLong(values) => {
+                values.iter().for_each(|&value| order.put_u32(&mut out, value as u32))
+            }
+            ExifValue::SignedRational(values) => {
+                values.iter().for_each(|&(numerator, denominator)| {
+                    order.put_u32(&mut out, numerator as u32);
+                    order.put_u32(&mut out, denominator as u32);
+                })
+            }
+            ExifValue::Float(values) => {
+                values.iter().for_each(|value| order.put_u32(&mut out, value.to_bits()))
+            }
+            ExifValue::Double(values) => {
+                values.iter().for_each(|value| order.put_u64(&mut out, value.to_bits()))
+            }
+            ExifValue::SubIfd(_) => unreachable!("sub-IFDs are written by write_ifd"),
+        }
+        out
+    }
+}
+
+fn join<T: Display>(values: &[T]) -> String {
+    values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+impl Display for ExifValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExifValue::Ascii(bytes) => {
+                let text = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+                write!(f, "{:?}", String::from_utf8_lossy(text))
+            }
+            ExifValue::Byte(bytes) | ExifValue::Undefined(bytes) => {
+                write!(f, "{} bytes", bytes.len())
+            }
+            ExifValue::Short(values) => write!(f, "{}", join(values)),
+            ExifValue::Long(values) => write!(f, "{}", join(values)),
+            ExifValue::Rational(values) => {
+                let values: Vec<String> = values
+                    .iter()
+                    .map(|(numerator, denominator)| format!("{}/{}", numerator, denominator))
+                    .collect();
+                write!(f, "{}", values.join(", "))
+            }
+            ExifValue::SignedByte(values) => write!(f, "{}", join(values)),
+            ExifValue::SignedShort(values) => write!(f, "{}", join(values)),
+            ExifValue::SignedLong(values) => write!(f, "{}", join(values)),
+            ExifValue::SignedRational(values) => {
+                let values: Vec<String> = values
+                    .iter()
+                    .map(|(numerator, denominator)| format!("{}/{}", numerator, denominator))
+                    .collect();
+                write!(f, "{}", values.join(", "))
+            }
+            ExifValue::Float(values) => write!(f, "{}", join(values)),
+            ExifValue::Double(values) => write!(f, "{}", join(values)),
+            ExifValue::SubIfd(ifd) => write!(f, "IFD with {} entries", ifd.entries.len()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IfdEntry {
+    pub tag: u16,
+    pub value: ExifValue,
+}
+
+/// One image file directory. Entries whose field type is unknown are
+/// dropped on parsing, as TIFF readers are told to ignore them.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Ifd {
+    pub entries: Vec<IfdEntry>,
+    /// JPEG thumbnail that `JPEGInterchangeFormat` points to, carried along
+    /// so it survives rewriting
+    pub thumbnail: Option<Vec<u8>>,
+}
+
+impl Ifd {
+    pub fn get(&self, tag: u16) -> Option<&ExifValue> {
+        self.entries.iter().find(|entry| entry.tag == tag).map(|entry| &entry.value)
+    }
+
+    fn remove_tag(&mut self, tag: u16) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|entry| entry.tag != tag);
+        let mut removed = before - self.entries.len();
+        for entry in self.entries.iter_mut() {
+            if let ExifValue::SubIfd(sub_ifd) = &mut entry.value {
+                removed += sub_ifd.remove_tag(tag);
+            }
+        }
+        if tag == THUMBNAIL_OFFSET || tag == THUMBNAIL_LENGTH {
+            self.thumbnail = None;
+            self.entries
+                .retain(|entry| entry.tag != THUMBNAIL_OFFSET && entry.tag != THUMBNAIL_LENGTH);
+        }
+        removed
+    }
+
+    fn collect<'a>(&'a self, name: String, out: &mut Vec<(String, &'a IfdEntry)>) {
+        for entry in self.entries.iter() {
+            out.push((name.clone(), entry));
+            if let ExifValue::SubIfd(sub_ifd) = &entry.value {
+                let sub_name = match entry.tag {
+                    EXIF_IFD => String::from("Exif"),
+                    GPS_IFD => String::from("GPS"),
+                    INTEROP_IFD => String::from("Interop"),
+                    other => format!("{:#06x}", other),
+                };
+                sub_ifd.collect(sub_name, out);
+            }
+        }
+    }
+}
+
+/// The TIFF structure stored in an eXIf chunk: a chain of IFDs (IFD0 for the
+/// image, IFD1 for the thumbnail) with their Exif, GPS and Interop sub-IFDs
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exif {
+    pub byte_order: ByteOrder,
+    pub ifds: Vec<Ifd>,
+}
+
+fn invalid(reason: &'static str) -> Error {
+    Error::InvalidChunkData {
+        chunk_type: String::from("eXIf"),
+        reason,
+    }
+}
+
+struct Parser<'a> {
+    data: &'a [u8],
+    order: ByteOrder,
+}
+
+impl Parser<'_> {
+    fn bytes(&self, offset: usize, length: usize) -> Result<&[u8]> {
+        offset
+            .checked_add(length)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or(invalid("offset points past the end of the data"))
+    }
+
+    /// Reads the IFD at `offset` and returns it with the offset of the next one
+    fn read_ifd(&self, offset: usize, depth: usize) -> Result<(Ifd, u32)> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("IFDs are nested too deeply"));
+        }
+        let count = self.order.u16(self.bytes(offset, 2)?) as usize;
+        let entries = self.bytes(offset + 2, count * 12 + 4)?;
+        let next = self.order.u32(&entries[count * 12..]);
+
+        let mut ifd = Ifd::default();
+        for entry in entries[..count * 12].chunks_exact(12) {
+            let tag = self.order.u16(entry);
+            let field_type = self.order.u16(&entry[2..]);
+            let count = self.order.u32(&entry[4..]) as usize;
+            let Some(size) = ExifValue::element_size(field_type) else {
+                continue;
+            };
+            let length = size.checked_mul(count).ok_or(invalid("entry is too large"))?;
+            let data = if length <= 4 {
+                &entry[8..8 + length]
+            } else {
+                self.bytes(self.order.u32(&entry[8..]) as usize, length)?
+            };
+
+            let is_pointer = matches!(tag, EXIF_IFD | GPS_IFD | INTEROP_IFD)
+                && matches!(field_type, 4 | 13)
+                && count == 1;
+            let value = if is_pointer {
+                let (sub_ifd, _) = self.read_ifd(self.order.u32(data) as usize, depth + 1)?;
+                ExifValue::SubIfd(sub_ifd)
+            } else {
+                ExifValue::decode(field_type, data, self.order)
+            };
+            ifd.entries.push(IfdEntry { tag, value });
+        }
+
+        let thumbnail = match (ifd.get(THUMBNAIL_OFFSET), ifd.get(THUMBNAIL_LENGTH)) {
+            (Some(ExifValue::Long(offset)), Some(ExifValue::Long(length)))
+                if offset.len() == 1 && length.len() == 1 =>
+            {
+                self.bytes(offset[0] as usize, length[0] as usize).ok()
+            }
+            _ => None,
+        };
+        match thumbnail {
+            Some(thumbnail) => ifd.thumbnail = Some(thumbnail.to_vec()),
+            // An offset we cannot follow would be wrong once rewritten
+            None => ifd
+                .entries
+                .retain(|entry| entry.tag != THUMBNAIL_OFFSET && entry.tag != THUMBNAIL_LENGTH),
+        }
+        Ok((ifd, next))
+    }
+}
+
+/// Writes `ifd` and everything it points to at the end of `out`, returning
+/// its offset and the position of its next-IFD pointer
+fn write_ifd(ifd: &Ifd, order: ByteOrder, out: &mut Vec<u8>) -> (usize, usize) {
+    // TIFF wants everything on word boundaries
+    if out.len() % 2 == 1 {
+        out.push(0);
+    }
+    let start = out.len();
+    order.put_u16(out, ifd.entries.len() as u16);
+    for entry in ifd.entries.iter() {
+        order.put_u16(out, entry.tag);
+        order.put_u16(out, entry.value.field_type());
+        order.put_u32(out, entry.value.count() as u32);
+        order.put_u32(out, 0);
+    }
+    let next_pointer = out.len();
+    order.put_u32(out, 0);
+
+    for (index, entry) in ifd.entries.iter().enumerate() {
+        let value_field = start + 2 + index * 12 + 8;
+        match &entry.value {
+            ExifValue::SubIfd(sub_ifd) => {
+                let (offset, _) = write_ifd(sub_ifd, order, out);
+                order.write_u32_at(out, value_field, offset as u32);
+            }
+            value => {
+                let bytes = value.encode(order);
+                if bytes.len() <= 4 {
+                    out[value_field..value_field + bytes.len()].copy_from_slice(&bytes);
+                } else {
+                    if out.len() % 2 == 1 {
+                        out.push(0);
+                    }
+                    let offset = out.len() as u32;
+                    out.extend_from_slice(&bytes);
+                    order.write_u32_at(out, value_field, offset);
+                }
+            }
+        }
+    }
+
+    if let Some(thumbnail) = &ifd.thumbnail {
+        if let Some(index) = ifd.entries.iter().position(|entry| entry.tag == THUMBNAIL_OFFSET) {
+            let offset = out.len() as u32;
+            out.extend_from_slice(thumbnail);
+            order.write_u32_at(out, start + 2 + index * 12 + 8, offset);
+        }
+    }
+    (start, next_pointer)
+}
+
+impl Exif {
+    /// The first IFD holding `tag`, searching sub-IFDs too
+    pub fn get(&self, tag: u16) -> Option<&ExifValue> {
+        self.entries()
+            .into_iter()
+            .find(|(_, entry)| entry.tag == tag)
+            .map(|(_, entry)| &entry.value)
+    }
+
+    /// Every entry with the name of the IFD it lives in (`IFD0`, `IFD1`,
+    /// `Exif`, `GPS`, `Interop`), sub-IFDs listed after their pointer
+    pub fn entries(&self) -> Vec<(String, &IfdEntry)> {
+        let mut entries = Vec::new();
+        for (index, ifd) in self.ifds.iter().enumerate() {
+            ifd.collect(format!("IFD{}", index), &mut entries);
+        }
+        entries
+    }
+
+    /// Deletes every entry with this tag in every IFD, returning how many
+    /// were removed. Deleting a pointer tag such as `GPS_IFD` drops the
+    /// whole sub-IFD. Tags in the GPS IFD use their own numbering, so small
+    /// tag numbers may match there too.
+    pub fn remove_tag(&mut self, tag: u16) -> usize {
+        self.ifds.iter_mut().map(|ifd| ifd.remove_tag(tag)).sum()
+    }
+
+    /// Serializes to TIFF bytes with fresh offsets
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = match self.byte_order {
+            ByteOrder::LittleEndian => b"II".to_vec(),
+            ByteOrder::BigEndian => b"MM".to_vec(),
+        };
+        self.byte_order.put_u16(&mut out, 42);
+        self.byte_order.put_u32(&mut out, 0);
+
+        let mut pointer = 4;
+        for ifd in self.ifds.iter() {
+            let (offset, next_pointer) = write_ifd(ifd, self.byte_order, &mut out);
+            self.byte_order.write_u32_at(&mut out, pointer, offset as u32);
+            pointer = next_pointer;
+        }
+        out
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(ChunkType::try_from(EXIF_CHUNK_TYPE).unwrap(), self.to_bytes())
+    }
+}
+
+impl TryFrom<&[u8]> for Exif {
+    type Error = Error;
+
+    fn try_from(data: &[u8]) -> Result<Exif> {
+        let byte_order = match data.get(..4) {
+            Some(b"II*\0") => ByteOrder::LittleEndian,
+            Some(b"MM\0*") => ByteOrder::BigEndian,
+            _ => return Err(invalid("missing TIFF header")),
+        };
+        let parser = Parser { data, order: byte_order };
+        let mut next = byte_order.u32(&data[4..]);
+        let mut ifds = Vec::new();
+        while next != 0 {
+            if ifds.len() == MAX_CHAINED_IFDS {
+                return Err(invalid("too many chained IFDs"));
+            }
+            let (ifd, following) = parser.read_ifd(next as usize, 0)?;
+            ifds.push(ifd);
+            next = following;
+        }
+        Ok(Exif { byte_order, ifds })
+    }
+}
+
+impl TryFrom<&Chunk> for Exif {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Exif> {
+        if chunk.chunk_type().bytes() != EXIF_CHUNK_TYPE {
+            return Err(Error::InvalidChunkType {
+                chunk_type: chunk.chunk_type().to_string(),
+                offset: 0,
+            });
+        }
+        Exif::try_from(chunk.data())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ascii(text: &str) -> ExifValue {
+        ExifValue::Ascii(format!("{}\0", text).into_bytes())
+    }
+
+    fn entry(tag: u16, value: ExifValue) -> IfdEntry {
+        IfdEntry { tag, value }
+    }
+
+    fn testing_exif(byte_order: ByteOrder) -> Exif {
+        let gps = Ifd {
+            entries: vec![
+                entry(0x0001, ascii("N")),
+                entry(0x0002, ExifValue::Rational(vec![(51, 1), (30, 1), (2613, 100)])),
+            ],
+            thumbnail: None,
+        };
+        let exif_ifd = Ifd {
+            entries: vec![
+                entry(0x829A, ExifValue::Rational(vec![(1, 250)])),
+                entry(BODY_SERIAL_NUMBER, ascii("SN123456")),
+            ],
+            thumbnail: None,
+        };
+        let ifd0 = Ifd {
+            entries: vec![
+                entry(MAKE, ascii("Pixel")),
+                entry(0x0112, ExifValue::Short(vec![1])),
+                entry(EXIF_IFD, ExifValue::SubIfd(exif_ifd)),
+                entry(GPS_IFD, ExifValue::SubIfd(gps)),
+            ],
+            thumbnail: None,
+        };
+        let ifd1 = Ifd {
+            entries: vec![
+                entry(THUMBNAIL_OFFSET, ExifValue::Long(vec![0])),
+                entry(THUMBNAIL_LENGTH, ExifValue::Long(vec![4])),
+            ],
+            thumbnail: Some(vec![0xFF, 0xD8, 0xFF, 0xD9]),
+        };
+        Exif { byte_order, ifds: vec![ifd0, ifd1] }
+    }
+
+    #[test]
+    fn test_round_trip_both_byte_orders() {
+        for byte_order in [ByteOrder::LittleEndian, ByteOrder::BigEndian] {
+            let exif = testing_exif(byte_order);
+            let parsed = Exif::try_from(&exif.to_chunk()).unwrap();
+            assert_eq!(parsed.ifds[0], exif.ifds[0]);
+            assert_eq!(parsed.ifds[1].thumbnail, exif.ifds[1].thumbnail);
+            // Only the thumbnail offset changes, and rewriting again is stable
+            assert_eq!(parsed.to_bytes(), exif.to_bytes());
+        }
+    }
+
+    #[test]
+    fn test_parse_handwritten_big_endian() {
+        // MM header, IFD0 at 8 with one SHORT entry (Orientation = 6)
+        let data = [
+            b'M', b'M', 0, 42, 0, 0, 0, 8, 0, 1, 0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0, 0, 0,
+            0, 0, 0, 0,
+        ];
+        let exif = Exif::try_from(&data[..]).unwrap();
+        assert_eq!(exif.byte_order, ByteOrder::BigEndian);
+        assert_eq!(exif.get(0x0112), Some(&ExifValue::Short(vec![6])));
+    }
+
+    #[test]
+    fn test_entries_are_listed_with_ifd_names() {
+        let exif = testing_exif(ByteOrder::LittleEndian);
+        let names: Vec<String> = exif
+            .entries()
+            .iter()
+            .map(|(ifd, entry)| format!("{} {:#06x}", ifd, entry.tag))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "IFD0 0x010f",
+                "IFD0 0x0112",
+                "IFD0 0x8769",
+                "Exif 0x829a",
+                "Exif 0xa431",
+                "IFD0 0x8825",
+                "GPS 0x0001",
+                "GPS 0x0002",
+                "IFD1 0x0201",
+                "IFD1 0x0202",
+            ]
+        );
+        assert_eq!(exif.get(MAKE).unwrap().to_string(), "\"Pixel\"");
+    }
+
+    #[test]
+    fn test_remove_gps_and_serial_number() {
+        let mut exif = testing_exif(ByteOrder::BigEndian);
+        assert_eq!(exif.remove_tag(GPS_IFD), 1);
+        assert_eq!(exif.remove_tag(BODY_SERIAL_NUMBER), 1);
+        assert_eq!(exif.remove_tag(BODY_SERIAL_NUMBER), 0);
+
+        let parsed = Exif::try_from(exif.to_bytes().as_slice()).unwrap();
+        assert_eq!(parsed.ifds[0], exif.ifds[0]);
+        assert!(parsed.get(GPS_IFD).is_none());
+        assert!(parsed.get(0x0002).is_none());
+        assert!(parsed.get(BODY_SERIAL_NUMBER).is_none());
+        assert_eq!(parsed.ifds[1].thumbnail, Some(vec![0xFF, 0xD8, 0xFF, 0xD9]));
+    }
+
+    #[test]
+    fn test_malformed_data() {
+        assert!(Exif::try_from(&b"not tiff"[..]).is_err());
+
+        let mut data = testing_exif(ByteOrder::LittleEndian).to_bytes();
+        data.truncate(30);
+        assert!(matches!(
+            Exif::try_from(data.as_slice()),
+            Err(Error::InvalidChunkData { .. })
+        ));
+
+        // IFD0 whose next pointer loops back to itself
+        let looping = [b'I', b'I', 42, 0, 8, 0, 0, 0, 0, 0, 8, 0, 0, 0];
+        assert!(Exif::try_from(&looping[..]).is_err());
+    }
+
+    #[test]
+    fn test_tag_from_name() {
+        assert_eq!(tag_from_name("GPSInfo"), Some(GPS_IFD));
+        assert_eq!(tag_from_name("bodyserialnumber"), Some(BODY_SERIAL_NUMBER));
+        assert_eq!(tag_from_name("0x9286"), Some(0x9286));
+        assert_eq!(tag_from_name("274"), Some(0x0112));
+        assert_eq!(tag_from_name("NotATag"), None);
+    }
+}
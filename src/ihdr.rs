@@ -0,0 +1,258 @@
+use std::fmt::Display;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{Error, Result};
+
+/// How each pixel is made up, as stored in the IHDR color type byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Grayscale,
+    Rgb,
+    Indexed,
+    GrayscaleAlpha,
+    Rgba,
+}
+
+impl ColorType {
+    pub fn from_byte(byte: u8) -> Option<ColorType> {
+        match byte {
+            0 => Some(ColorType::Grayscale),
+            2 => Some(ColorType::Rgb),
+            3 => Some(ColorType::Indexed),
+            4 => Some(ColorType::GrayscaleAlpha),
+            6 => Some(ColorType::Rgba),
+            _ => None,
+        }
+    }
+
+    pub fn to_byte(self) -> u8 {
+        match self {
+            ColorType::Grayscale => 0,
+            ColorType::Rgb => 2,
+            ColorType::Indexed => 3,
+            ColorType::GrayscaleAlpha => 4,
+            ColorType::Rgba => 6,
+        }
+    }
+
+    /// Number of samples stored per pixel
+    pub fn channels(self) -> usize {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    /// Bit depths the PNG spec allows for this color type
+    pub fn allowed_bit_depths(self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16],
+        }
+    }
+}
+
+impl Display for ColorType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Rgb => "RGB",
+            ColorType::Indexed => "indexed",
+            ColorType::GrayscaleAlpha => "grayscale + alpha",
+            ColorType::Rgba => "RGBA",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interlace {
+    None,
+    Adam7,
+}
+
+/// The decoded contents of an IHDR chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    /// Always 0 (deflate) for valid files
+    pub compression: u8,
+    /// Always 0 (adaptive filtering) for valid files
+    pub filter: u8,
+    pub interlace: Interlace,
+}
+
+impl Ihdr {
+    pub const CHUNK_TYPE: [u8; 4] = *b"IHDR";
+    const LENGTH: usize = 13;
+
+    /// Bits used by one pixel, across all its samples
+    pub fn bits_per_pixel(&self) -> usize {
+        self.color_type.channels() * self.bit_depth as usize
+    }
+
+    /// Bytes in one unfiltered row of `width` pixels, without the filter byte
+    pub fn row_bytes(&self, width: u32) -> usize {
+        (width as usize * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = Vec::with_capacity(Ihdr::LENGTH);
+        data.extend_from_slice(&self.width.to_be_bytes());
+        data.extend_from_slice(&self.height.to_be_bytes());
+        data.push(self.bit_depth);
+        data.push(self.color_type.to_byte());
+        data.push(self.compression);
+        data.push(self.filter);
+        data.push(match self.interlace {
+            Interlace::None => 0,
+            Interlace::Adam7 => 1,
+        });
+        Chunk::new(ChunkType::try_from(Ihdr::CHUNK_TYPE).unwrap(), data)
+    }
+}
+
+fn invalid(reason: &'static str) -> Error {
+    Error::InvalidChunkData {
+        chunk_type: String::from("IHDR"),
+        reason,
+    }
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Ihdr> {
+        if chunk.chunk_type().bytes() != Ihdr::CHUNK_TYPE {
+            return Err(Error::InvalidChunkType {
+                chunk_type: chunk.chunk_type().to_string(),
+                offset: 0,
+            });
+        }
+        let data = chunk.data();
+        if data.len() != Ihdr::LENGTH {
+            return Err(invalid("IHDR data must be 13 bytes long"));
+        }
+
+        let width = u32::from_be_bytes(data[0..4].try_into().unwrap());
+        let height = u32::from_be_bytes(data[4..8].try_into().unwrap());
+        if width == 0 || height == 0 {
+            return Err(invalid("width and height must be at least 1"));
+        }
+        if width > Chunk::MAX_LENGTH || height > Chunk::MAX_LENGTH {
+            return Err(invalid("width and height must not exceed 2^31 - 1"));
+        }
+
+        let bit_depth = data[8];
+        let color_type = ColorType::from_byte(data[9]).ok_or(invalid("unknown color type"))?;
+        if !color_type.allowed_bit_depths().contains(&bit_depth) {
+            return Err(invalid("bit depth is not allowed for this color type"));
+        }
+        if data[10] != 0 {
+            return Err(invalid("unknown compression method"));
+        }
+        if data[11] != 0 {
+            return Err(invalid("unknown filter method"));
+        }
+        let interlace = match data[12] {
+            0 => Interlace::None,
+            1 => Interlace::Adam7,
+            _ => return Err(invalid("unknown interlace method")),
+        };
+
+        Ok(Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            compression: data[10],
+            filter: data[11],
+            interlace,
+        })
+    }
+}
+
+impl Display for Ihdr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}x{}, {}-bit {}, {}",
+            self.width,
+            self.height,
+            self.bit_depth,
+            self.color_type,
+            match self.interlace {
+                Interlace::None => "non-interlaced",
+                Interlace::Adam7 => "Adam7 interlaced",
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn ihdr_chunk(data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str("IHDR").unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_parse_ihdr() {
+        // IHDR of the dice test image: 50x50, 8-bit RGBA
+        let chunk = ihdr_chunk(&[0, 0, 0, 50, 0, 0, 0, 50, 8, 6, 0, 0, 0]);
+        let ihdr = Ihdr::try_from(&chunk).unwrap();
+
+        assert_eq!(ihdr.width, 50);
+        assert_eq!(ihdr.height, 50);
+        assert_eq!(ihdr.bit_depth, 8);
+        assert_eq!(ihdr.color_type, ColorType::Rgba);
+        assert_eq!(ihdr.interlace, Interlace::None);
+        assert_eq!(ihdr.bits_per_pixel(), 32);
+        assert_eq!(ihdr.to_string(), "50x50, 8-bit RGBA, non-interlaced");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = [0, 0, 1, 0, 0, 0, 0, 3, 2, 3, 0, 0, 1];
+        let ihdr = Ihdr::try_from(&ihdr_chunk(&data)).unwrap();
+        assert_eq!(ihdr.to_chunk().data(), &data);
+    }
+
+    #[test]
+    fn test_row_bytes() {
+        let ihdr = Ihdr::try_from(&ihdr_chunk(&[0, 0, 0, 3, 0, 0, 0, 1, 1, 0, 0, 0, 0])).unwrap();
+        assert_eq!(ihdr.row_bytes(3), 1);
+        assert_eq!(ihdr.row_bytes(9), 2);
+    }
+
+    #[test]
+    fn test_invalid_bit_depth_for_color_type() {
+        // 4-bit RGB is not allowed
+        let chunk = ihdr_chunk(&[0, 0, 0, 1, 0, 0, 0, 1, 4, 2, 0, 0, 0]);
+        assert!(matches!(Ihdr::try_from(&chunk), Err(Error::InvalidChunkData { .. })));
+
+        // 16-bit indexed is not allowed
+        let chunk = ihdr_chunk(&[0, 0, 0, 1, 0, 0, 0, 1, 16, 3, 0, 0, 0]);
+        assert!(Ihdr::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_invalid_fields() {
+        let valid = [0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0];
+        for (index, value) in [(3, 0), (9, 1), (10, 1), (11, 1), (12, 2), (0, 0x80)] {
+            let mut data = valid;
+            data[index] = value;
+            assert!(Ihdr::try_from(&ihdr_chunk(&data)).is_err(), "byte {}", index);
+        }
+        assert!(Ihdr::try_from(&ihdr_chunk(&valid[..12])).is_err());
+    }
+}
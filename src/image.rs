@@ -0,0 +1,378 @@
+use crate::ancillary::{Plte, Trns};
+use crate::error::{Error, Result};
+use crate::ihdr::ColorType;
+use crate::scanline::{self, FilterType, ImageData, Pass};
+
+/// The channels each decoded pixel carries, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelLayout {
+    Gray,
+    GrayAlpha,
+    Rgb,
+    Rgba,
+}
+
+impl ChannelLayout {
+    pub fn channels(self) -> usize {
+        match self {
+            ChannelLayout::Gray => 1,
+            ChannelLayout::GrayAlpha => 2,
+            ChannelLayout::Rgb => 3,
+            ChannelLayout::Rgba => 4,
+        }
+    }
+
+    /// The PNG color type that stores this layout without a palette
+    pub fn color_type(self) -> ColorType {
+        match self {
+            ChannelLayout::Gray => ColorType::Grayscale,
+            ChannelLayout::GrayAlpha => ColorType::GrayscaleAlpha,
+            ChannelLayout::Rgb => ColorType::Rgb,
+            ChannelLayout::Rgba => ColorType::Rgba,
+        }
+    }
+
+    pub fn has_alpha(self) -> bool {
+        matches!(self, ChannelLayout::GrayAlpha | ChannelLayout::Rgba)
+    }
+}
+
+/// Decoded pixels, row by row with the channels of each pixel interleaved.
+/// Samples below 8 bits still take a whole byte each, keeping their
+/// original range (0 or 1 for 1-bit images), and 16-bit samples take two
+/// bytes, big-endian.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub layout: ChannelLayout,
+    /// 1, 2, 4, 8 or 16
+    pub bit_depth: u8,
+    pub data: Vec<u8>,
+}
+
+fn invalid(chunk_type: &str, reason: &'static str) -> Error {
+    Error::InvalidChunkData {
+        chunk_type: chunk_type.to_string(),
+        reason,
+    }
+}
+
+/// Reads the `index`-th sample of an unfiltered line packed at `bit_depth`
+fn packed_sample(line: &[u8], index: usize, bit_depth: u8) -> u8 {
+    match bit_depth {
+        8 => line[index],
+        _ => {
+            let bit = index * bit_depth as usize;
+            let shift = 8 - bit_depth as usize - bit % 8;
+            (line[bit / 8] >> shift) & ((1 << bit_depth) - 1)
+        }
+    }
+}
+
+impl Image {
+    /// A blank (all zero) image
+    pub fn new(width: u32, height: u32, layout: ChannelLayout, bit_depth: u8) -> Image {
+        let mut image = Image { width, height, layout, bit_depth, data: Vec::new() };
+        image.data = vec![0; image.pixel_bytes() * width as usize * height as usize];
+        image
+    }
+
+    pub fn bytes_per_sample(&self) -> usize {
+        if self.bit_depth == 16 {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Bytes taken by one pixel in `data`
+    pub fn pixel_bytes(&self) -> usize {
+        self.layout.channels() * self.bytes_per_sample()
+    }
+
+    fn offset(&self, x: u32, y: u32, channel: usize) -> usize {
+        ((y as usize * self.width as usize + x as usize) * self.layout.channels() + channel)
+            * self.bytes_per_sample()
+    }
+
+    pub fn sample(&self, x: u32, y: u32, channel: usize) -> u16 {
+        let offset = self.offset(x, y, channel);
+        if self.bit_depth == 16 {
+            u16::from_be_bytes([self.data[offset], self.data[offset + 1]])
+        } else {
+            self.data[offset] as u16
+        }
+    }
+
+    pub fn set_sample(&mut self, x: u32, y: u32, channel: usize, value: u16) {
+        let offset = self.offset(x, y, channel);
+        if self.bit_depth == 16 {
+            self.data[offset..offset + 2].copy_from_slice(&value.to_be_bytes());
+        } else {
+            self.data[offset] = value as u8;
+        }
+    }
+
+    /// Unfilters and de-interlaces `image_data`. Indexed images are expanded
+    /// through `palette` to 8-bit RGB, or RGBA when `transparency` is given.
+    /// A tRNS color key on grayscale or RGB images is not applied.
+    pub fn decode(
+        image_data: &ImageData,
+        palette: Option<&Plte>,
+        transparency: Option<&Trns>,
+    ) -> Result<Image> {
+        let ihdr = &image_data.ihdr;
+        let palette = match ihdr.color_type {
+            ColorType::Indexed => {
+                Some(palette.ok_or(invalid("PLTE", "indexed image without a palette"))?)
+            }
+            _ => None,
+        };
+        let alphas = match transparency {
+            Some(Trns::Palette(alphas)) => alphas.as_slice(),
+            _ => &[],
+        };
+        let (layout, bit_depth) = match ihdr.color_type {
+            ColorType::Indexed if transparency.is_some() => (ChannelLayout::Rgba, 8),
+            ColorType::Indexed => (ChannelLayout::Rgb, 8),
+            ColorType::Grayscale => (ChannelLayout::Gray, ihdr.bit_depth),
+            ColorType::GrayscaleAlpha => (ChannelLayout::GrayAlpha, ihdr.bit_depth),
+            ColorType::Rgb => (ChannelLayout::Rgb, ihdr.bit_depth),
+            ColorType::Rgba => (ChannelLayout::Rgba, ihdr.bit_depth),
+        };
+
+        let mut image = Image::new(ihdr.width, ihdr.height, layout, bit_depth);
+        let pixel_bytes = image.pixel_bytes();
+        let bpp = ihdr.bits_per_pixel().div_ceil(8);
+        let channels = ihdr.color_type.channels();
+        let mut rest = image_data.bytes.as_slice();
+
+        for pass in Pass::for_image(ihdr) {
+            let stride = ihdr.row_bytes(pass.width);
+            let mut previous = vec![0; stride];
+            for y in 0..pass.height {
+                if rest.len() <= stride {
+                    return Err(invalid("IDAT", "image data is shorter than the image"));
+                }
+                let (stored, after) = rest.split_at(stride + 1);
+                rest = after;
+                let filter = FilterType::from_byte(stored[0])
+                    .ok_or_else(|| invalid("IDAT", "unknown filter type"))?;
+                let mut line = stored[1..].to_vec();
+                scanline::unfilter(filter, &mut line, &previous, bpp);
+
+                for x in 0..pass.width {
+                    let (image_x, image_y) = pass.image_position(x, y);
+                    let target = image.offset(image_x, image_y, 0);
+                    let pixel = &mut image.data[target..target + pixel_bytes];
+                    match palette {
+                        Some(palette) => {
+                            let index = packed_sample(&line, x as usize, ihdr.bit_depth) as usize;
+                            let color = palette
+                                .entries
+                                .get(index)
+                                .ok_or_else(|| invalid("PLTE", "palette index out of range"))?;
+                            pixel[..3].copy_from_slice(color);
+                            if layout == ChannelLayout::Rgba {
+                                pixel[3] = alphas.get(index).copied().unwrap_or(255);
+                            }
+                        }
+                        None if bit_depth == 16 => {
+                            let start = x as usize * pixel_bytes;
+                            pixel.copy_from_slice(&line[start..start + pixel_bytes]);
+                        }
+                        None => {
+                            for (channel, sample) in pixel.iter_mut().enumerate() {
+                                let index = x as usize * channels + channel;
+                                *sample = packed_sample(&line, index, bit_depth);
+                            }
+                        }
+                    }
+                }
+                previous = line;
+            }
+        }
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ancillary::KnownChunk;
+    use crate::chunk::Chunk;
+    use crate::ihdr::{Ihdr, Interlace};
+    use crate::png::Png;
+    use crate::zlib;
+
+    /// Small deterministic generator so the tests need no extra dependency
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u8 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 >> 24) as u8
+        }
+    }
+
+    /// Builds a PNG whose stored scanlines are random bytes behind random
+    /// filter types. Any decoder has to turn that into the same pixels.
+    fn random_png(ihdr: Ihdr, with_trns: bool, seed: u64) -> Png {
+        let mut random = XorShift(seed);
+        let mut raw = Vec::new();
+        for pass in Pass::for_image(&ihdr) {
+            for _ in 0..pass.height {
+                raw.push(random.next() % 5);
+                raw.extend((0..ihdr.row_bytes(pass.width)).map(|_| random.next()));
+            }
+        }
+
+        let mut chunks = vec![ihdr.to_chunk()];
+        if ihdr.color_type == ColorType::Indexed {
+            let size = 1usize << ihdr.bit_depth;
+            let entries = (0..size).map(|_| [random.next(), random.next(), random.next()]);
+            chunks.push(KnownChunk::Plte(Plte { entries: entries.collect() }).to_chunk().unwrap());
+            if with_trns {
+                let alphas = (0..size / 2).map(|_| random.next()).collect();
+                chunks.push(KnownChunk::Trns(Trns::Palette(alphas)).to_chunk().unwrap());
+            }
+        }
+        // Split the stream over a few IDAT chunks like real encoders do
+        for part in zlib::deflate(&raw).chunks(64) {
+            chunks.push(Chunk::new("IDAT".parse().unwrap(), part.to_vec()));
+        }
+        chunks.push(Chunk::new("IEND".parse().unwrap(), Vec::new()));
+        Png::from_chunks(chunks)
+    }
+
+    /// Decodes with the `png` crate, expanding palettes but nothing else, and
+    /// unpacks sub-byte samples to one per byte like `Image` does
+    fn reference_decode(png: &Png) -> Vec<u8> {
+        let bytes = png.as_bytes();
+        let mut decoder = ::png::Decoder::new(bytes.as_slice());
+        let indexed = png.ihdr().unwrap().color_type == ColorType::Indexed;
+        decoder.set_transformations(if indexed {
+            ::png::Transformations::EXPAND
+        } else {
+            ::png::Transformations::IDENTITY
+        });
+        let mut reader = decoder.read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).unwrap();
+
+        let bit_depth = info.bit_depth as u8;
+        if bit_depth >= 8 {
+            buffer.truncate(info.buffer_size());
+            return buffer;
+        }
+        let samples_per_row = info.width as usize * info.color_type.samples();
+        buffer
+            .chunks(info.line_size)
+            .take(info.height as usize)
+            .flat_map(|line| {
+                (0..samples_per_row).map(move |index| packed_sample(line, index, bit_depth))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_reference_decoder() {
+        let mut seed = 1;
+        for color_type in [
+            ColorType::Grayscale,
+            ColorType::Rgb,
+            ColorType::Indexed,
+            ColorType::GrayscaleAlpha,
+            ColorType::Rgba,
+        ] {
+            for &bit_depth in color_type.allowed_bit_depths() {
+                for interlace in [Interlace::None, Interlace::Adam7] {
+                    for (width, height) in [(1, 1), (7, 5), (33, 17)] {
+                        seed += 1;
+                        let ihdr = Ihdr {
+                            width,
+                            height,
+                            bit_depth,
+                            color_type,
+                            compression: 0,
+                            filter: 0,
+                            interlace,
+                        };
+                        let png = random_png(ihdr, seed % 2 == 0, seed);
+                        let image = png.decode_image().unwrap();
+                        assert_eq!(
+                            image.data,
+                            reference_decode(&png),
+                            "{} {}-bit {:?} {}x{}",
+                            color_type,
+                            bit_depth,
+                            interlace,
+                            width,
+                            height
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_dice() {
+        let png = Png::try_from(&crate::png::tests::PNG_FILE[..]).unwrap();
+        let image = png.decode_image().unwrap();
+        assert_eq!((image.width, image.height), (50, 50));
+        assert_eq!(image.layout, ChannelLayout::Rgba);
+        assert_eq!(image.data.len(), 50 * 50 * 4);
+        assert_eq!(image.data, reference_decode(&png));
+    }
+
+    #[test]
+    fn test_palette_expansion() {
+        let ihdr = Ihdr {
+            width: 4,
+            height: 1,
+            bit_depth: 2,
+            color_type: ColorType::Indexed,
+            compression: 0,
+            filter: 0,
+            interlace: Interlace::None,
+        };
+        // Indices 0, 1, 2, 1 packed into one byte
+        let data = ImageData::inflate(ihdr, &zlib::deflate(&[0, 0b00_01_10_01])).unwrap();
+        let palette = Plte { entries: vec![[255, 0, 0], [0, 255, 0], [0, 0, 255]] };
+
+        let image = Image::decode(&data, Some(&palette), None).unwrap();
+        assert_eq!(image.layout, ChannelLayout::Rgb);
+        assert_eq!(image.data, [255, 0, 0, 0, 255, 0, 0, 0, 255, 0, 255, 0]);
+
+        let transparency = Trns::Palette(vec![0]);
+        let image = Image::decode(&data, Some(&palette), Some(&transparency)).unwrap();
+        assert_eq!(image.layout, ChannelLayout::Rgba);
+        assert_eq!(&image.data[..8], [255, 0, 0, 0, 0, 255, 0, 255]);
+
+        let short = Plte { entries: vec![[0, 0, 0]] };
+        assert!(Image::decode(&data, Some(&short), None).is_err());
+        assert!(Image::decode(&data, None, None).is_err());
+    }
+
+    #[test]
+    fn test_short_image_data() {
+        let png = Png::try_from(&crate::png::tests::PNG_FILE[..]).unwrap();
+        let mut data = png.image_data().unwrap();
+        data.bytes.pop();
+        assert!(Image::decode(&data, None, None).is_err());
+        data.bytes.clear();
+        assert!(Image::decode(&data, None, None).is_err());
+    }
+
+    #[test]
+    fn test_samples() {
+        let mut image = Image::new(2, 2, ChannelLayout::GrayAlpha, 16);
+        image.set_sample(1, 1, 1, 0xABCD);
+        assert_eq!(image.sample(1, 1, 1), 0xABCD);
+        assert_eq!(&image.data[14..], [0xAB, 0xCD]);
+    }
+}
@@ -1,3 +1,37 @@
-mod chunk;
-mod chunk_type;
-mod png;
+pub mod analysis;
+pub mod ancillary;
+pub mod chunk;
+pub mod chunk_type;
+pub mod compression;
+pub mod container;
+pub mod crypto;
+pub mod encoder;
+pub mod error;
+pub mod exif;
+pub mod ihdr;
+pub mod image;
+pub mod payload;
+pub mod png;
+pub mod reader;
+pub mod recipients;
+pub mod scanline;
+pub mod scrub;
+pub mod signature;
+pub mod stego;
+pub mod text;
+pub mod writer;
+mod zlib;
+
+pub use ancillary::KnownChunk;
+pub use chunk::Chunk;
+pub use chunk_type::ChunkType;
+pub use encoder::EncoderOptions;
+pub use error::{Error, Result};
+pub use ihdr::Ihdr;
+pub use image::Image;
+pub use png::{InsertionPolicy, Png};
+pub use reader::ChunkReader;
+pub use scrub::ScrubPolicy;
+pub use stego::LsbOptions;
+pub use text::TextEntry;
+pub use writer::PngWriter;
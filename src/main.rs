@@ -1,4 +1,34 @@
-fn main() {
-    let val = "Rust";
-    println!("result -> {:?}", val.as_bytes());
+use std::process::ExitCode;
+
+use clap::Parser;
+
+mod args;
+mod commands;
+
+use args::{Cli, Command};
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Encode(args) => commands::encode(args),
+        Command::Decode(args) => commands::decode(args),
+        Command::Remove(args) => commands::remove(args),
+        Command::Print(args) => commands::print(args),
+        Command::Keygen(args) => commands::keygen(args),
+        Command::Sign(args) => commands::sign(args),
+        Command::Verify(args) => commands::verify(args),
+        Command::Text(args) => commands::text(args),
+        Command::Exif(args) => commands::exif(args),
+        Command::Scrub(args) => commands::scrub(args),
+        Command::Analyze(args) => commands::analyze(args),
+        Command::Trailer(args) => commands::trailer(args),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
 }
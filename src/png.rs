@@ -1,87 +1,384 @@
-use std::str::FromStr;
+use std::fmt::Display;
 
+use crate::ancillary::{string_to_latin1, KnownChunk, Plte, Trns};
 use crate::chunk::Chunk;
-use crate::chunk_type::ChunkType;
+use crate::encoder::{self, EncoderOptions};
+use crate::error::{Error, Result};
+use crate::exif::Exif;
+use crate::ihdr::Ihdr;
+use crate::image::Image;
+use crate::scanline::ImageData;
+use crate::scrub::{ScrubPolicy, ScrubReport};
+use crate::text::{self, TextEntry};
 
 
-struct Png {
-    header: [u8; 8],
-    chunks: Vec<Chunk>
+/// Where `Png::insert_chunk` places a new chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InsertionPolicy {
+    /// Before the first IDAT if the chunk is not safe to copy, otherwise before IEND
+    #[default]
+    Auto,
+    BeforeIend,
+    BeforeFirstIdat,
+    /// At the very end, even after IEND. Parsing the file again puts the
+    /// chunk in the trailer.
+    Append,
 }
 
-type ChunkError = &'static str;
+#[derive(Debug)]
+pub struct Png {
+    header: [u8; 8],
+    chunks: Vec<Chunk>,
+    /// Whatever followed IEND in the file, kept byte for byte
+    trailer: Vec<u8>,
+}
 
 impl Png {
-    const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+    pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
-    fn from_chunks(chunks: Vec<Chunk>) -> Png {
+    pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
         Png {
             header: Png::STANDARD_HEADER,
-            chunks
+            chunks,
+            trailer: Vec::new(),
         }
     }
-    fn append_chunk(&mut self, chunk: Chunk) {}
-    // fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk, ChunkError> {}
-    // fn header(&self) -> &[u8; 8] {}
-    fn chunks(&self) -> &[Chunk] {
-        &self.chunks
+
+    pub fn append_chunk(&mut self, chunk: Chunk) {
+        self.chunks.push(chunk);
+    }
+
+    /// Inserts a chunk where the policy says it belongs and returns its index.
+    /// Strict decoders drop or reject anything after IEND, so message chunks
+    /// should go through here rather than `append_chunk`. If the anchor chunk
+    /// is missing, the policy falls back to the next one and finally to appending.
+    pub fn insert_chunk(&mut self, chunk: Chunk, policy: InsertionPolicy) -> usize {
+        let first_idat = self.index_of("IDAT", 0);
+        let iend = self.index_of("IEND", 0);
+
+        let index = match policy {
+            InsertionPolicy::Auto if !chunk.chunk_type().is_safe_to_copy() => first_idat.or(iend),
+            InsertionPolicy::Auto | InsertionPolicy::BeforeIend => iend,
+            InsertionPolicy::BeforeFirstIdat => first_idat.or(iend),
+            InsertionPolicy::Append => None,
+        }
+        .unwrap_or(self.chunks.len());
+
+        self.chunks.insert(index, chunk);
+        index
+    }
+
+    /// Removes the first chunk of the given type and returns it
+    pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
+        let position = self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string() == chunk_type);
+        match position {
+            Some(index) => Ok(self.chunks.remove(index)),
+            None => Err(Error::ChunkNotFound {
+                chunk_type: chunk_type.to_string()
+            })
+        }
+    }
+
+    /// Removes every chunk of the given type, returning them in file order
+    pub fn remove_all(&mut self, chunk_type: &str) -> Vec<Chunk> {
+        let (removed, kept) = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .partition(|chunk| chunk.chunk_type().to_string() == chunk_type);
+        self.chunks = kept;
+        removed
+    }
+
+    /// Inserts a chunk so that it ends up at `index`, shifting later chunks back
+    pub fn insert_at(&mut self, index: usize, chunk: Chunk) -> Result<()> {
+        if index > self.chunks.len() {
+            return Err(Error::IndexOutOfBounds { index, len: self.chunks.len() });
+        }
+        self.chunks.insert(index, chunk);
+        Ok(())
+    }
+
+    pub fn remove_at(&mut self, index: usize) -> Result<Chunk> {
+        if index >= self.chunks.len() {
+            return Err(Error::IndexOutOfBounds { index, len: self.chunks.len() });
+        }
+        Ok(self.chunks.remove(index))
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Chunk> {
+        self.chunks.get(index)
+    }
+
+    /// Position in `chunks()` of the `occurrence`-th chunk of the given type,
+    /// counting from 0
+    pub fn index_of(&self, chunk_type: &str, occurrence: usize) -> Option<usize> {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| chunk.chunk_type().to_string() == chunk_type)
+            .map(|(index, _)| index)
+            .nth(occurrence)
+    }
+
+    /// Parses and validates the IHDR chunk
+    pub fn ihdr(&self) -> Result<Ihdr> {
+        let chunk = self.chunk_by_type("IHDR").ok_or(Error::ChunkNotFound {
+            chunk_type: String::from("IHDR")
+        })?;
+        Ihdr::try_from(chunk)
+    }
+
+    /// Concatenates every IDAT chunk and inflates the result, ready to be
+    /// split into scanlines
+    pub fn image_data(&self) -> Result<ImageData> {
+        let ihdr = self.ihdr()?;
+        let compressed: Vec<u8> = self
+            .chunks_by_type("IDAT")
+            .flat_map(|chunk| chunk.data().iter().copied())
+            .collect();
+        ImageData::inflate(ihdr, &compressed)
+    }
+
+    /// Decodes the pixels, expanding indexed images through PLTE and tRNS
+    pub fn decode_image(&self) -> Result<Image> {
+        let image_data = self.image_data()?;
+        let color_type = image_data.ihdr.color_type;
+        let palette = self
+            .chunk_by_type("PLTE")
+            .map(|chunk| Plte::parse(chunk.data()))
+            .transpose()?;
+        let transparency = self
+            .chunk_by_type("tRNS")
+            .map(|chunk| Trns::parse(chunk.data(), color_type))
+            .transpose()?;
+        Image::decode(&image_data, palette.as_ref(), transparency.as_ref())
+    }
+
+    /// Replaces the pixels with `image`, encoded with `options`. Ancillary
+    /// chunks stay where they are, except that PLTE, tRNS, bKGD, sBIT and hIST
+    /// are dropped when the color type or bit depth changes, since they are
+    /// read in terms of those.
+    pub fn set_image(&mut self, image: &Image, options: &EncoderOptions) -> Result<()> {
+        let old = self.ihdr()?;
+        let mut encoded = encoder::encode(image, options)?.chunks;
+        let ihdr = encoded.remove(0);
+        let idats = encoded.into_iter().filter(|chunk| chunk.chunk_type().to_string() == "IDAT");
+
+        if old.color_type != image.layout.color_type() || old.bit_depth != image.bit_depth {
+            for chunk_type in ["PLTE", "tRNS", "bKGD", "sBIT", "hIST"] {
+                self.remove_all(chunk_type);
+            }
+        }
+        let ihdr_index = self.index_of("IHDR", 0).expect("the IHDR was just parsed");
+        self.chunks[ihdr_index] = ihdr;
+        let index = self
+            .index_of("IDAT", 0)
+            .or(self.index_of("IEND", 0))
+            .unwrap_or(self.chunks.len());
+        self.remove_all("IDAT");
+        self.chunks.splice(index..index, idats);
+        Ok(())
+    }
+
+    /// Typed views of every PLTE and standard ancillary chunk, in file order.
+    /// Fails on the first malformed one.
+    pub fn known_chunks(&self) -> Result<Vec<KnownChunk>> {
+        let color_type = self.ihdr()?.color_type;
+        let mut known = Vec::new();
+        for chunk in self.chunks.iter() {
+            if let Some(parsed) = KnownChunk::parse(chunk, color_type)? {
+                known.push(parsed);
+            }
+        }
+        Ok(known)
+    }
+
+    /// Every tEXt, zTXt and iTXt entry, in file order. Fails on the first
+    /// malformed one.
+    pub fn text_entries(&self) -> Result<Vec<TextEntry>> {
+        self.chunks
+            .iter()
+            .filter(|chunk| TextEntry::is_text_chunk(chunk.chunk_type()))
+            .map(TextEntry::try_from)
+            .collect()
+    }
+
+    /// The first text entry with the given keyword
+    pub fn text(&self, keyword: &str) -> Result<Option<TextEntry>> {
+        match self.text_indices(keyword).first() {
+            Some(&index) => TextEntry::try_from(&self.chunks[index]).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Stores `entry`, replacing every existing entry with its keyword. The
+    /// new chunk takes the place of the first one replaced, or goes before
+    /// IEND if there was none.
+    pub fn set_text(&mut self, entry: &TextEntry) -> Result<()> {
+        let chunk = entry.to_chunk()?;
+        match self.text_indices(&entry.keyword).first() {
+            Some(&index) => {
+                self.remove_text(&entry.keyword);
+                self.chunks.insert(index, chunk);
+            }
+            None => {
+                self.insert_chunk(chunk, InsertionPolicy::Auto);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes every text chunk with the given keyword, returning them in
+    /// file order
+    pub fn remove_text(&mut self, keyword: &str) -> Vec<Chunk> {
+        let Some(keyword) = string_to_latin1(keyword) else {
+            return Vec::new();
+        };
+        let (removed, kept) = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .partition(|chunk| text::raw_keyword(chunk) == Some(keyword.as_slice()));
+        self.chunks = kept;
+        removed
+    }
+
+    /// Removes every chunk the policy does not keep, leaving the pixels and
+    /// chunk order untouched
+    pub fn scrub(&mut self, policy: &ScrubPolicy) -> ScrubReport {
+        let (kept, removed) = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .partition(|chunk| policy.keeps(chunk.chunk_type()));
+        self.chunks = kept;
+        ScrubReport { removed }
+    }
+
+    /// Parses the eXIf chunk, if there is one
+    pub fn exif(&self) -> Result<Option<Exif>> {
+        self.chunk_by_type("eXIf").map(Exif::try_from).transpose()
     }
-    fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
-        for (_, chunk) in self.chunks.iter().enumerate() {
-            let given_chunk_type = ChunkType::from_str(chunk_type).unwrap();
-            if *chunk.chunk_type() == given_chunk_type {
-                return Some(&chunk);
+
+    /// Replaces the eXIf chunk, or adds one before the first IDAT as the
+    /// spec requires
+    pub fn set_exif(&mut self, exif: &Exif) {
+        let chunk = exif.to_chunk();
+        match self.index_of("eXIf", 0) {
+            Some(index) => self.chunks[index] = chunk,
+            None => {
+                self.insert_chunk(chunk, InsertionPolicy::BeforeFirstIdat);
             }
         }
-        return None;
     }
-    // fn as_bytes(&self) -> Vec<u8> {}
+
+    fn text_indices(&self, keyword: &str) -> Vec<usize> {
+        let Some(keyword) = string_to_latin1(keyword) else {
+            return Vec::new();
+        };
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| text::raw_keyword(chunk) == Some(keyword.as_slice()))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    pub fn header(&self) -> &[u8; 8] {
+        &self.header
+    }
+
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    /// Bytes that followed the IEND chunk, written back after the chunks by
+    /// `as_bytes`. Decoders ignore them, which makes them a common place to
+    /// hide data.
+    pub fn trailer(&self) -> &[u8] {
+        &self.trailer
+    }
+
+    pub fn set_trailer(&mut self, trailer: Vec<u8>) {
+        self.trailer = trailer;
+    }
+
+    /// Removes the trailer and returns it
+    pub fn take_trailer(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.trailer)
+    }
+
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
+        self.chunks_by_type(chunk_type).next()
+    }
+
+    /// Every chunk of the given type, in file order
+    pub fn chunks_by_type(&self, chunk_type: &str) -> impl Iterator<Item = &Chunk> {
+        let chunk_type = chunk_type.to_string();
+        self.chunks
+            .iter()
+            .filter(move |chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.header.to_vec();
+        for chunk in self.chunks.iter() {
+            bytes.append(&mut chunk.as_bytes());
+        }
+        bytes.extend_from_slice(&self.trailer);
+        bytes
+    }
+}
+
+impl Display for Png {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for chunk in self.chunks.iter() {
+            writeln!(f, "{}", chunk)?;
+        }
+        Ok(())
+    }
 }
 
 impl TryFrom<&[u8]> for Png {
-    type Error = &'static str;
-    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+    type Error = Error;
+    fn try_from(value: &[u8]) -> Result<Self> {
         // Compute standard signature
-        let signature = &value[0..8];
-        for (index, value) in Png::STANDARD_HEADER.iter().enumerate() {
-            if signature[index] != *value {
-                return Err("The standard header is invalid");
-            }
+        let signature = value
+            .get(0..8)
+            .ok_or(Error::Truncated { offset: 0, needed: 8 })?;
+        if signature != Png::STANDARD_HEADER {
+            return Err(Error::InvalidSignature);
         }
 
-        // Compute chunks
+        // Compute chunks, stopping at IEND: anything after it is kept as the
+        // trailer rather than parsed
         let mut chunks: Vec<Chunk> = Vec::new();
         let mut index = 8;
         while index < value.len() {
-            let result = Chunk::try_from(&value[index ..]);
-            match result {
-                Ok(chunk) => {
-                    // 12 comprises of
-                    // 4 bytes denoting chunk_length
-                    // 4 bytes denoting chunk_type
-                    // 4 bytes denoting crc
-                    index += 12 + chunk.length() as usize ;
-                    chunks.push(chunk);
-                },
-                Err(error) => {
-                    return Err(error);
-                }
+            let chunk = Chunk::try_from(&value[index..]).map_err(|error| error.offset_by(index))?;
+            // 12 comprises of
+            // 4 bytes denoting chunk_length
+            // 4 bytes denoting chunk_type
+            // 4 bytes denoting crc
+            index += 12 + chunk.length() as usize;
+            let is_iend = &chunk.chunk_type().bytes() == b"IEND";
+            chunks.push(chunk);
+            if is_iend {
+                break;
             }
         }
-        Ok(
-            Png {
-                header: Png::STANDARD_HEADER,
-                chunks
-            }
-        )
+        Ok(Png {
+            header: Png::STANDARD_HEADER,
+            chunks,
+            trailer: value[index.min(value.len())..].to_vec(),
+        })
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::chunk_type::ChunkType;
     use crate::chunk::Chunk;
+    use crate::exif::{ByteOrder, ExifValue, Ifd, IfdEntry, GPS_IFD};
     use std::str::FromStr;
     use std::convert::TryFrom;
 
@@ -98,7 +395,7 @@ mod tests {
         Png::from_chunks(chunks)
     }
 
-    fn chunk_from_strings(chunk_type: &str, data: &str) -> Result<Chunk, ChunkError> {
+    fn chunk_from_strings(chunk_type: &str, data: &str) -> Result<Chunk> {
 
         let chunk_type = ChunkType::from_str(chunk_type)?;
         let data: Vec<u8> = data.bytes().collect();
@@ -173,6 +470,55 @@ mod tests {
         assert!(png.is_err());
     }
 
+    #[test]
+    fn test_error_offset_points_into_file() {
+        let mut bytes = testing_png().as_bytes();
+        // Flip a data byte in the second chunk, which starts after the
+        // signature and the 12 + 20 bytes of the first chunk
+        let second_chunk = 8 + 12 + 20;
+        bytes[second_chunk + 8] ^= 1;
+
+        let error = Png::try_from(bytes.as_ref()).unwrap_err();
+
+        assert!(matches!(
+            error,
+            Error::CrcMismatch { offset, .. } if offset == second_chunk
+        ));
+    }
+
+    #[test]
+    fn test_invalid_signature_error() {
+        let mut bytes = testing_png().as_bytes();
+        bytes[0] = 13;
+        assert_eq!(Png::try_from(bytes.as_ref()).unwrap_err(), Error::InvalidSignature);
+    }
+
+    #[test]
+    fn test_truncated_file_never_panics() {
+        for end in 0..PNG_FILE.len() {
+            // Cutting exactly on a chunk boundary still leaves a parseable file
+            if let Ok(png) = Png::try_from(&PNG_FILE[..end]) {
+                assert_eq!(png.as_bytes(), &PNG_FILE[..end]);
+            }
+        }
+        assert_eq!(
+            Png::try_from(&PNG_FILE[..5]).unwrap_err(),
+            Error::Truncated { offset: 0, needed: 8 }
+        );
+    }
+
+    #[test]
+    fn test_corrupted_file_never_panics() {
+        let mut seed: u32 = 0x1234_5678;
+        for _ in 0..2000 {
+            let mut bytes = PNG_FILE.to_vec();
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let position = seed as usize % bytes.len();
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            bytes[position] = (seed >> 16) as u8;
+            let _ = Png::try_from(bytes.as_ref());
+        }
+    }
 
     #[test]
     fn test_list_chunks() {
@@ -198,49 +544,342 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
     }
 
-    // #[test]
-    // fn test_remove_chunk() {
-    //     let mut png = testing_png();
-    //     png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
-    //     png.remove_chunk("TeSt").unwrap();
-    //     let chunk = png.chunk_by_type("TeSt");
-    //     assert!(chunk.is_none());
-    // }
-
-    // #[test]
-    // fn test_png_from_image_file() {
-    //     let png = Png::try_from(&PNG_FILE[..]);
-    //     assert!(png.is_ok());
-    // }
-
-    // #[test]
-    // fn test_as_bytes() {
-    //     let png = Png::try_from(&PNG_FILE[..]).unwrap();
-    //     let actual = png.as_bytes();
-    //     let expected: Vec<u8> = PNG_FILE.to_vec();
-    //     assert_eq!(actual, expected);
-    // }
-
-    // #[test]
-    // fn test_png_trait_impls() {
-    //     let chunk_bytes: Vec<u8> = testing_chunks()
-    //         .into_iter()
-    //         .flat_map(|chunk| chunk.as_bytes())
-    //         .collect();
-
-    //     let bytes: Vec<u8> = Png::STANDARD_HEADER
-    //         .iter()
-    //         .chain(chunk_bytes.iter())
-    //         .copied()
-    //         .collect();
-
-    //     let png: Png = TryFrom::try_from(bytes.as_ref()).unwrap();
-
-    //     let _png_string = format!("{}", png);
-    // }
+    #[test]
+    fn test_remove_chunk() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        png.remove_chunk("TeSt").unwrap();
+        let chunk = png.chunk_by_type("TeSt");
+        assert!(chunk.is_none());
+    }
+
+    fn image_png() -> Png {
+        Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("IDAT", "pixels").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect()
+    }
+
+    #[test]
+    fn test_insert_safe_to_copy_before_iend() {
+        let mut png = image_png();
+        let chunk = chunk_from_strings("ruSt", "Message").unwrap();
+        let index = png.insert_chunk(chunk, InsertionPolicy::Auto);
+
+        assert_eq!(index, 2);
+        assert_eq!(chunk_types(&png), vec!["IHDR", "IDAT", "ruSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_insert_unsafe_to_copy_before_idat() {
+        let mut png = image_png();
+        let chunk = chunk_from_strings("ruST", "Message").unwrap();
+        let index = png.insert_chunk(chunk, InsertionPolicy::Auto);
+
+        assert_eq!(index, 1);
+        assert_eq!(chunk_types(&png), vec!["IHDR", "ruST", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_insert_policy_fallbacks() {
+        let mut png = testing_png();
+        png.insert_chunk(chunk_from_strings("ruST", "Message").unwrap(), InsertionPolicy::Auto);
+        assert_eq!(&png.chunks()[3].chunk_type().to_string(), "ruST");
+
+        let mut png = image_png();
+        png.insert_chunk(chunk_from_strings("ruSt", "Message").unwrap(), InsertionPolicy::Append);
+        assert_eq!(chunk_types(&png), vec!["IHDR", "IDAT", "IEND", "ruSt"]);
+    }
+
+    #[test]
+    fn test_chunks_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("FrSt", "I am a second first chunk").unwrap());
+
+        let data: Vec<String> = png
+            .chunks_by_type("FrSt")
+            .map(|chunk| chunk.data_as_string().unwrap())
+            .collect();
+
+        assert_eq!(data, vec!["I am the first chunk", "I am a second first chunk"]);
+        assert_eq!(png.chunks_by_type("TeSt").count(), 0);
+    }
+
+    #[test]
+    fn test_index_of() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("FrSt", "I am a second first chunk").unwrap());
+
+        assert_eq!(png.index_of("FrSt", 0), Some(0));
+        assert_eq!(png.index_of("FrSt", 1), Some(3));
+        assert_eq!(png.index_of("FrSt", 2), None);
+    }
+
+    #[test]
+    fn test_insert_and_remove_at() {
+        let mut png = testing_png();
+        png.insert_at(1, chunk_from_strings("TeSt", "Message").unwrap()).unwrap();
+
+        assert_eq!(&png.get(1).unwrap().chunk_type().to_string(), "TeSt");
+        assert_eq!(&png.get(2).unwrap().chunk_type().to_string(), "miDl");
+
+        let removed = png.remove_at(1).unwrap();
+        assert_eq!(&removed.data_as_string().unwrap(), "Message");
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_index_out_of_bounds() {
+        let mut png = testing_png();
+        assert_eq!(
+            png.insert_at(4, chunk_from_strings("TeSt", "Message").unwrap()).unwrap_err(),
+            Error::IndexOutOfBounds { index: 4, len: 3 }
+        );
+        assert!(png.remove_at(3).is_err());
+        assert!(png.get(3).is_none());
+    }
+
+    #[test]
+    fn test_remove_all() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "one").unwrap());
+        png.insert_at(0, chunk_from_strings("TeSt", "two").unwrap()).unwrap();
+
+        let removed = png.remove_all("TeSt");
+
+        assert_eq!(removed.len(), 2);
+        assert_eq!(&removed[0].data_as_string().unwrap(), "two");
+        assert_eq!(png.chunks().len(), 3);
+        assert!(png.remove_all("TeSt").is_empty());
+    }
+
+    #[test]
+    fn test_remove_missing_chunk() {
+        let mut png = testing_png();
+        assert!(png.remove_chunk("TeSt").is_err());
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_header() {
+        let png = testing_png();
+        assert_eq!(png.header(), &Png::STANDARD_HEADER);
+    }
+
+    #[test]
+    fn test_png_from_image_file() {
+        let png = Png::try_from(&PNG_FILE[..]);
+        assert!(png.is_ok());
+    }
+
+    #[test]
+    fn test_ihdr() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let ihdr = png.ihdr().unwrap();
+        assert_eq!((ihdr.width, ihdr.height), (50, 50));
+
+        assert!(matches!(testing_png().ihdr(), Err(Error::ChunkNotFound { .. })));
+    }
+
+    #[test]
+    fn test_text_entries() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert!(png.text_entries().unwrap().is_empty());
+
+        png.set_text(&TextEntry::new("Author", "Jane", false)).unwrap();
+        png.set_text(&TextEntry::new("Description", "Two dice", true)).unwrap();
+        png.set_text(&TextEntry::new("Title", "サイコロ", false)).unwrap();
+
+        let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        let entries: Vec<String> =
+            png.text_entries().unwrap().iter().map(|entry| entry.to_string()).collect();
+        assert_eq!(entries, vec!["Author: Jane", "Description: Two dice", "Title: サイコロ"]);
+        assert_eq!(png.text("Description").unwrap().unwrap().text, "Two dice");
+        assert_eq!(png.text("Missing").unwrap(), None);
+        assert_eq!(png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+    }
+
+    #[test]
+    fn test_set_text_replaces_in_place() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.set_text(&TextEntry::new("Author", "Jane", false)).unwrap();
+        png.set_text(&TextEntry::new("Comment", "first", false)).unwrap();
+        png.append_chunk(TextEntry::new("Author", "duplicate", true).to_chunk().unwrap());
+
+        png.set_text(&TextEntry::new("Author", "John", false)).unwrap();
+
+        let entries: Vec<String> =
+            png.text_entries().unwrap().iter().map(|entry| entry.to_string()).collect();
+        assert_eq!(entries, vec!["Author: John", "Comment: first"]);
+    }
+
+    #[test]
+    fn test_remove_text() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.set_text(&TextEntry::new("Author", "Jane", false)).unwrap();
+        png.append_chunk(TextEntry::new("Author", "again", true).to_chunk().unwrap());
+
+        assert_eq!(png.remove_text("Author").len(), 2);
+        assert!(png.remove_text("Author").is_empty());
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_exif() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.exif().unwrap(), None);
+
+        let gps = IfdEntry { tag: GPS_IFD, value: ExifValue::SubIfd(Ifd::default()) };
+        let mut exif = Exif {
+            byte_order: ByteOrder::BigEndian,
+            ifds: vec![Ifd { entries: vec![gps], thumbnail: None }],
+        };
+        png.set_exif(&exif);
+        assert_eq!(png.index_of("eXIf", 0), png.index_of("IDAT", 0).map(|index| index - 1));
+
+        exif.remove_tag(GPS_IFD);
+        png.set_exif(&exif);
+        assert_eq!(png.chunks_by_type("eXIf").count(), 1);
+        assert_eq!(png.exif().unwrap(), Some(exif));
+    }
+
+    #[test]
+    fn test_scrub() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.set_text(&TextEntry::new("Author", "Jane", true)).unwrap();
+
+        let report = png.scrub(&ScrubPolicy::default());
+        let removed: Vec<String> =
+            report.removed.iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(removed, vec!["pHYs", "zTXt"]);
+        assert_eq!(report.removed_bytes(), 21 + report.removed[1].length() as usize + 12);
+
+        let kept: Vec<String> =
+            png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(kept, vec!["IHDR", "sRGB", "gAMA", "IDAT", "RuSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_image_data() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let data = png.image_data().unwrap();
+        assert_eq!(data.bytes.len(), 50 * (1 + 50 * 4));
+
+        let scanlines = data.scanlines().unwrap();
+        assert_eq!(scanlines.len(), 50);
+        assert!(scanlines.iter().all(|scanline| scanline.data.len() == 200));
+    }
+
+    #[test]
+    fn test_trailer() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend_from_slice(b"not a chunk at all");
+        let mut png = Png::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(png.trailer(), b"not a chunk at all");
+        assert_eq!(png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+        assert_eq!(png.as_bytes(), bytes);
+
+        assert_eq!(png.take_trailer(), b"not a chunk at all");
+        assert_eq!(png.as_bytes(), PNG_FILE);
+        png.set_trailer(b"new".to_vec());
+        assert!(png.as_bytes().ends_with(b"IEND\xaeB`\x82new"));
+    }
+
+    #[test]
+    fn test_chunk_after_iend_is_trailer() {
+        let mut png = image_png();
+        png.insert_chunk(chunk_from_strings("ruSt", "Message").unwrap(), InsertionPolicy::Append);
+        let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        assert_eq!(chunk_types(&png), vec!["IHDR", "IDAT", "IEND"]);
+        assert_eq!(png.trailer().len(), 12 + 7);
+
+        // Without IEND every chunk is parsed and there is no trailer
+        let png = Png::try_from(testing_png().as_bytes().as_slice()).unwrap();
+        assert_eq!(png.chunks().len(), 3);
+        assert!(png.trailer().is_empty());
+    }
+
+    #[test]
+    fn test_set_image() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut image = png.decode_image().unwrap();
+        image.data[0] ^= 1;
+        png.set_image(&image, &EncoderOptions::default()).unwrap();
+        assert_eq!(chunk_types(&png)[..4], ["IHDR", "sRGB", "gAMA", "pHYs"]);
+        assert_eq!(chunk_types(&png)[chunk_types(&png).len() - 2..], ["RuSt", "IEND"]);
+        assert_eq!(png.decode_image().unwrap(), image);
+    }
+
+    #[test]
+    fn test_set_image_drops_palette() {
+        let mut bytes = Vec::new();
+        let mut encoder = ::png::Encoder::new(&mut bytes, 2, 1);
+        encoder.set_color(::png::ColorType::Indexed);
+        encoder.set_palette(vec![255, 0, 0, 0, 0, 255]);
+        encoder.set_trns(vec![128]);
+        encoder.write_header().unwrap().write_image_data(&[0, 1]).unwrap();
+        let mut png = Png::try_from(bytes.as_slice()).unwrap();
+
+        let image = png.decode_image().unwrap();
+        png.set_image(&image, &EncoderOptions::default()).unwrap();
+        assert!(png.chunk_by_type("PLTE").is_none());
+        assert!(png.chunk_by_type("tRNS").is_none());
+        assert_eq!(png.decode_image().unwrap().data, [255, 0, 0, 128, 0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_known_chunks() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let types: Vec<String> = png
+            .known_chunks()
+            .unwrap()
+            .iter()
+            .map(|known| String::from_utf8_lossy(&known.chunk_type()).into_owned())
+            .collect();
+        assert_eq!(types, vec!["sRGB", "gAMA", "pHYs"]);
+    }
+
+    #[test]
+    fn test_as_bytes() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let actual = png.as_bytes();
+        let expected: Vec<u8> = PNG_FILE.to_vec();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_as_bytes_round_trip() {
+        let png = testing_png();
+        let bytes = png.as_bytes();
+        let parsed = Png::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(parsed.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_png_trait_impls() {
+        let chunk_bytes: Vec<u8> = testing_chunks()
+            .into_iter()
+            .flat_map(|chunk| chunk.as_bytes())
+            .collect();
+
+        let bytes: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .chain(chunk_bytes.iter())
+            .copied()
+            .collect();
+
+        let png: Png = TryFrom::try_from(bytes.as_ref()).unwrap();
+
+        let _png_string = format!("{}", png);
+    }
 
     // This is the raw bytes for a shrunken version of the `dice.png` image on Wikipedia
-    const PNG_FILE: [u8; 4803] = [
+    pub(crate) const PNG_FILE: [u8; 4803] = [
         137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 50, 0, 0, 0, 50, 8,
         6, 0, 0, 0, 30, 63, 136, 177, 0, 0, 0, 1, 115, 82, 71, 66, 0, 174, 206, 28, 233, 0, 0, 0,
         4, 103, 65, 77, 65, 0, 0, 177, 143, 11, 252, 97, 5, 0, 0, 0, 9, 112, 72, 89, 115, 0, 0, 14,
@@ -0,0 +1,240 @@
+use std::io::{self, Read};
+
+use crate::chunk::Chunk;
+use crate::error::{Error, Result};
+use crate::png::Png;
+
+/// Reads a PNG one chunk at a time from any `Read` implementation.
+/// The signature is checked when the reader is created and every chunk's CRC
+/// is checked as it is yielded, so only one chunk is held in memory at once.
+/// Like `Png::try_from` it stops after IEND; whatever follows is left unread
+/// in the underlying reader, see `into_inner`.
+///
+/// ```no_run
+/// use std::{fs::File, io::BufReader};
+/// use rust_png_cryptex::ChunkReader;
+///
+/// let file = BufReader::new(File::open("image.png")?);
+/// for chunk in ChunkReader::new(file)? {
+///     let chunk = chunk?;
+///     if chunk.chunk_type().to_string() == "ruSt" {
+///         println!("{}", chunk.data_as_string().unwrap_or_default());
+///         break;
+///     }
+/// }
+/// # Ok::<(), rust_png_cryptex::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct ChunkReader<R: Read> {
+    reader: R,
+    offset: usize,
+    finished: bool,
+}
+
+impl<R: Read> ChunkReader<R> {
+    /// Consumes and validates the PNG signature
+    pub fn new(mut reader: R) -> Result<ChunkReader<R>> {
+        let mut signature = [0; 8];
+        if read_up_to(&mut reader, &mut signature)? < signature.len() {
+            return Err(Error::Truncated { offset: 0, needed: 8 });
+        }
+        if signature != Png::STANDARD_HEADER {
+            return Err(Error::InvalidSignature);
+        }
+
+        Ok(ChunkReader {
+            reader,
+            offset: 8,
+            finished: false,
+        })
+    }
+
+    /// Number of bytes consumed from the underlying reader so far
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads the next chunk, or `None` if the input ends cleanly on a chunk boundary
+    fn read_chunk(&mut self) -> Result<Option<Chunk>> {
+        let start = self.offset;
+
+        let mut header = [0; 8];
+        let filled = read_up_to(&mut self.reader, &mut header)?;
+        if filled == 0 {
+            return Ok(None);
+        }
+        if filled < 4 {
+            return Err(Error::Truncated { offset: start, needed: 4 });
+        }
+        let length = Chunk::parse_length(header[0..4].try_into().unwrap())
+            .map_err(|error| error.offset_by(start))?;
+        if filled < 8 {
+            return Err(Error::Truncated { offset: start + 4, needed: 4 });
+        }
+        let chunk_type = Chunk::parse_type(header[4..8].try_into().unwrap())
+            .map_err(|error| error.offset_by(start))?;
+
+        // Grow the buffer as data arrives rather than trusting the length field
+        // with an up-front allocation
+        let mut data = Vec::new();
+        (&mut self.reader).take(length as u64).read_to_end(&mut data)?;
+        if data.len() < length as usize {
+            return Err(Error::Truncated { offset: start + 8, needed: length as usize });
+        }
+
+        let mut crc = [0; 4];
+        if read_up_to(&mut self.reader, &mut crc)? < crc.len() {
+            return Err(Error::Truncated {
+                offset: start + 8 + length as usize,
+                needed: 4,
+            });
+        }
+
+        let chunk = Chunk::from_parts(chunk_type, data.into_boxed_slice(), u32::from_be_bytes(crc))
+            .map_err(|error| error.offset_by(start))?;
+        self.offset = start + 12 + length as usize;
+        Ok(Some(chunk))
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let result = self.read_chunk().transpose();
+        // Stop after IEND, the end of input or the first error, the stream
+        // position is meaningless once a chunk has failed to parse
+        self.finished = match &result {
+            Some(Ok(chunk)) => chunk.chunk_type().bytes() == *b"IEND",
+            _ => true,
+        };
+        result
+    }
+}
+
+/// Like `read_exact`, but reports how many bytes were read instead of
+/// failing when the input ends early
+fn read_up_to<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(count) => filled += count,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error.into()),
+        }
+    }
+    Ok(filled)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("FrSt").unwrap(), b"I am the first chunk".to_vec()),
+            Chunk::new(ChunkType::from_str("miDl").unwrap(), b"I am another chunk".to_vec()),
+            Chunk::new(ChunkType::from_str("LASt").unwrap(), b"I am the last chunk".to_vec()),
+        ])
+    }
+
+    #[test]
+    fn test_reads_every_chunk() {
+        let bytes = testing_png().as_bytes();
+        let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+
+        let types: Vec<String> = reader
+            .by_ref()
+            .map(|chunk| chunk.unwrap().chunk_type().to_string())
+            .collect();
+
+        assert_eq!(types, vec!["FrSt", "miDl", "LASt"]);
+        assert_eq!(reader.offset(), bytes.len());
+    }
+
+    #[test]
+    fn test_matches_slice_parser() {
+        let bytes = testing_png().as_bytes();
+        let streamed: Vec<u8> = ChunkReader::new(bytes.as_slice())
+            .unwrap()
+            .flat_map(|chunk| chunk.unwrap().as_bytes())
+            .collect();
+        assert_eq!(streamed, &bytes[8..]);
+    }
+
+    #[test]
+    fn test_stops_at_iend() {
+        let mut png = testing_png();
+        png.append_chunk(Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()));
+        let mut bytes = png.as_bytes();
+        let end = bytes.len();
+        bytes.extend_from_slice(b"PK\x03\x04 not a chunk");
+
+        let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        let chunks: Vec<Chunk> = reader.by_ref().map(|chunk| chunk.unwrap()).collect();
+        assert_eq!(chunks.last().unwrap().chunk_type().to_string(), "IEND");
+        assert_eq!(reader.offset(), end);
+        assert_eq!(reader.into_inner(), b"PK\x03\x04 not a chunk");
+    }
+
+    #[test]
+    fn test_invalid_signature() {
+        let mut bytes = testing_png().as_bytes();
+        bytes[1] = 0;
+        assert_eq!(ChunkReader::new(bytes.as_slice()).unwrap_err(), Error::InvalidSignature);
+        assert_eq!(
+            ChunkReader::new(&bytes[..3]).unwrap_err(),
+            Error::Truncated { offset: 0, needed: 8 }
+        );
+    }
+
+    #[test]
+    fn test_truncated_stream() {
+        let bytes = testing_png().as_bytes();
+        // Cut the second chunk in the middle of its data
+        let second_chunk = 8 + 12 + 20;
+        let mut reader = ChunkReader::new(&bytes[..second_chunk + 10]).unwrap();
+
+        assert!(reader.next().unwrap().is_ok());
+        assert_eq!(
+            reader.next().unwrap().unwrap_err(),
+            Error::Truncated { offset: second_chunk + 8, needed: 18 }
+        );
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_crc_error_offset() {
+        let mut bytes = testing_png().as_bytes();
+        let second_chunk = 8 + 12 + 20;
+        bytes[second_chunk + 8] ^= 1;
+
+        let error = ChunkReader::new(bytes.as_slice())
+            .unwrap()
+            .find_map(|chunk| chunk.err())
+            .unwrap();
+
+        assert!(matches!(error, Error::CrcMismatch { offset, .. } if offset == second_chunk));
+    }
+
+    #[test]
+    fn test_hostile_length_does_not_allocate_up_front() {
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        bytes.extend_from_slice(&[0x7f, 0xff, 0xff, 0xff, b'R', b'u', b'S', b't', 1, 2, 3]);
+
+        let result = ChunkReader::new(bytes.as_slice()).unwrap().next().unwrap();
+
+        assert_eq!(result.unwrap_err(), Error::Truncated { offset: 16, needed: 0x7fff_ffff });
+    }
+}
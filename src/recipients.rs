@@ -0,0 +1,271 @@
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::crypto::check_message_chunk_type;
+use crate::error::{Error, Result};
+
+/// Chunk type used for messages encrypted to public keys: ancillary,
+/// private, safe to copy
+pub const RECIPIENT_CHUNK_TYPE: [u8; 4] = *b"reCp";
+
+const MAGIC: [u8; 4] = *b"rcpt";
+const VERSION: u8 = 1;
+/// X25519 key agreement, HKDF-SHA256 and ChaCha20-Poly1305
+const X25519_CHACHA20POLY1305: u8 = 1;
+
+const FILE_KEY_LENGTH: usize = 32;
+const TAG_LENGTH: usize = 16;
+/// Ephemeral public key followed by the wrapped file key
+const STANZA_LENGTH: usize = 32 + FILE_KEY_LENGTH + TAG_LENGTH;
+const NONCE_LENGTH: usize = 12;
+const WRAP_INFO: &[u8] = b"rust-png-cryptex/X25519";
+
+/// Generates a new identity, returning the secret key and the public key
+/// to hand out to senders
+pub fn generate_identity() -> (StaticSecret, PublicKey) {
+    let mut bytes = [0; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let secret = StaticSecret::from(bytes);
+    let public = PublicKey::from(&secret);
+    (secret, public)
+}
+
+/// Formats a key as 64 lowercase hex digits
+pub fn key_to_hex(key: &[u8; 32]) -> String {
+    key.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub(crate) fn key_from_hex(hex: &str) -> Result<[u8; 32]> {
+    let hex = hex.trim();
+    // from_str_radix alone would also take a sign, e.g. "+f"
+    if hex.len() != 64 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(Error::InvalidKey { reason: "expected 64 hex digits" });
+    }
+    let mut key = [0; 32];
+    for (index, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * index..2 * index + 2], 16)
+            .map_err(|_| Error::InvalidKey { reason: "expected 64 hex digits" })?;
+    }
+    Ok(key)
+}
+
+pub fn public_key_from_hex(hex: &str) -> Result<PublicKey> {
+    key_from_hex(hex).map(PublicKey::from)
+}
+
+pub fn secret_key_from_hex(hex: &str) -> Result<StaticSecret> {
+    key_from_hex(hex).map(StaticSecret::from)
+}
+
+/// Derives the key that wraps the file key for one recipient
+fn wrap_key(shared: &[u8; 32], ephemeral: &PublicKey, recipient: &PublicKey) -> [u8; 32] {
+    let salt = [ephemeral.as_bytes().as_slice(), recipient.as_bytes()].concat();
+    let mut key = [0; 32];
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(WRAP_INFO, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+/// Returns true if `data` starts like an envelope produced by `seal`
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Encrypts `message` so that any one of `recipients` can decrypt it.
+///
+/// A random file key encrypts the message, and one stanza per recipient
+/// carries that file key wrapped with an X25519 shared secret, in the style
+/// of age. The envelope is laid out as
+/// `magic | version | algorithm | count | stanzas | nonce | ciphertext`,
+/// with everything before the ciphertext authenticated as associated data.
+pub fn seal(message: &[u8], recipients: &[PublicKey]) -> Result<Vec<u8>> {
+    if recipients.is_empty() || recipients.len() > u8::MAX as usize {
+        return Err(Error::InvalidKey { reason: "between 1 and 255 recipients are needed" });
+    }
+
+    let mut file_key = [0; FILE_KEY_LENGTH];
+    OsRng.fill_bytes(&mut file_key);
+
+    let mut envelope = Vec::new();
+    envelope.extend_from_slice(&MAGIC);
+    envelope.push(VERSION);
+    envelope.push(X25519_CHACHA20POLY1305);
+    envelope.push(recipients.len() as u8);
+
+    for recipient in recipients {
+        let (ephemeral_secret, ephemeral) = generate_identity();
+        let shared = ephemeral_secret.diffie_hellman(recipient);
+        if !shared.was_contributory() {
+            return Err(Error::InvalidKey { reason: "recipient is a low order point" });
+        }
+
+        // Every wrap key is used exactly once, so a fixed nonce is safe
+        let key = wrap_key(shared.as_bytes(), &ephemeral, recipient);
+        let wrapped = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .encrypt(&Nonce::default(), file_key.as_slice())
+            .expect("wrapping a 32 byte key cannot fail");
+
+        envelope.extend_from_slice(ephemeral.as_bytes());
+        envelope.extend_from_slice(&wrapped);
+    }
+
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    envelope.extend_from_slice(&nonce);
+
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&file_key))
+        .encrypt(&nonce, Payload { msg: message, aad: &envelope })
+        .map_err(|_| Error::InvalidPayload { reason: "message is too long to encrypt" })?;
+    envelope.extend_from_slice(&ciphertext);
+    Ok(envelope)
+}
+
+/// Decrypts an envelope produced by `seal` with one recipient's secret key
+pub fn open(envelope: &[u8], identity: &StaticSecret) -> Result<Vec<u8>> {
+    if envelope.len() < MAGIC.len() + 3 {
+        return Err(Error::InvalidPayload { reason: "encrypted message is too short" });
+    }
+    if !is_encrypted(envelope) {
+        return Err(Error::InvalidPayload { reason: "message is not encrypted to recipients" });
+    }
+    if envelope[4] != VERSION {
+        return Err(Error::UnsupportedVersion { version: envelope[4] });
+    }
+    if envelope[5] != X25519_CHACHA20POLY1305 {
+        return Err(Error::UnsupportedAlgorithm { algorithm: envelope[5] });
+    }
+
+    let count = envelope[6] as usize;
+    let stanzas_start = MAGIC.len() + 3;
+    let header_length = stanzas_start + count * STANZA_LENGTH + NONCE_LENGTH;
+    if envelope.len() < header_length {
+        return Err(Error::InvalidPayload { reason: "encrypted message is too short" });
+    }
+    let (header, ciphertext) = envelope.split_at(header_length);
+
+    let public = PublicKey::from(identity);
+    let file_key = header[stanzas_start..header_length - NONCE_LENGTH]
+        .chunks_exact(STANZA_LENGTH)
+        .find_map(|stanza| {
+            let ephemeral = PublicKey::from(<[u8; 32]>::try_from(&stanza[..32]).unwrap());
+            let shared = identity.diffie_hellman(&ephemeral);
+            let key = wrap_key(shared.as_bytes(), &ephemeral, &public);
+            ChaCha20Poly1305::new(Key::from_slice(&key))
+                .decrypt(&Nonce::default(), &stanza[32..])
+                .ok()
+        })
+        .ok_or(Error::DecryptionFailed)?;
+
+    let nonce = Nonce::from_slice(&header[header_length - NONCE_LENGTH..]);
+    ChaCha20Poly1305::new(Key::from_slice(&file_key))
+        .decrypt(nonce, Payload { msg: ciphertext, aad: header })
+        .map_err(|_| Error::DecryptionFailed)
+}
+
+/// Encrypts `message` to `recipients` in a new chunk, whose type has to be
+/// ancillary and private
+pub fn encrypt_chunk(
+    chunk_type: ChunkType,
+    message: &[u8],
+    recipients: &[PublicKey],
+) -> Result<Chunk> {
+    check_message_chunk_type(&chunk_type)?;
+    Chunk::try_new(chunk_type, seal(message, recipients)?)
+}
+
+pub fn decrypt_chunk(chunk: &Chunk, identity: &StaticSecret) -> Result<Vec<u8>> {
+    open(chunk.data(), identity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::Png;
+
+    #[test]
+    fn test_every_recipient_can_decrypt() {
+        let (alice_secret, alice) = generate_identity();
+        let (bob_secret, bob) = generate_identity();
+
+        let envelope = seal(b"meet me at noon", &[alice, bob]).unwrap();
+
+        assert!(is_encrypted(&envelope));
+        assert_eq!(open(&envelope, &alice_secret).unwrap(), b"meet me at noon");
+        assert_eq!(open(&envelope, &bob_secret).unwrap(), b"meet me at noon");
+    }
+
+    #[test]
+    fn test_other_identity_cannot_decrypt() {
+        let (_, alice) = generate_identity();
+        let (eve_secret, _) = generate_identity();
+
+        let envelope = seal(b"meet me at noon", &[alice]).unwrap();
+
+        assert_eq!(open(&envelope, &eve_secret).unwrap_err(), Error::DecryptionFailed);
+    }
+
+    #[test]
+    fn test_tampered_stanza_list_is_rejected() {
+        let (alice_secret, alice) = generate_identity();
+        let (_, bob) = generate_identity();
+        let mut envelope = seal(b"meet me at noon", &[alice, bob]).unwrap();
+
+        // Corrupt Bob's stanza; Alice can still unwrap her file key but the
+        // header no longer authenticates
+        let bob_stanza = MAGIC.len() + 3 + STANZA_LENGTH;
+        envelope[bob_stanza] ^= 1;
+
+        assert_eq!(open(&envelope, &alice_secret).unwrap_err(), Error::DecryptionFailed);
+    }
+
+    #[test]
+    fn test_truncated_envelope() {
+        let (alice_secret, alice) = generate_identity();
+        let envelope = seal(b"meet me at noon", &[alice]).unwrap();
+
+        assert!(matches!(
+            open(&envelope[..40], &alice_secret),
+            Err(Error::InvalidPayload { .. })
+        ));
+    }
+
+    #[test]
+    fn test_no_recipients() {
+        assert!(matches!(seal(b"hi", &[]), Err(Error::InvalidKey { .. })));
+    }
+
+    #[test]
+    fn test_hex_keys() {
+        let (secret, public) = generate_identity();
+        let parsed = public_key_from_hex(&key_to_hex(public.as_bytes())).unwrap();
+        assert_eq!(parsed, public);
+
+        let parsed = secret_key_from_hex(&key_to_hex(&secret.to_bytes())).unwrap();
+        assert_eq!(PublicKey::from(&parsed), public);
+
+        assert!(public_key_from_hex("not a key").is_err());
+        assert!(public_key_from_hex(&"+f".repeat(32)).is_err());
+        assert!(public_key_from_hex(&"0g".repeat(32)).is_err());
+        assert!(public_key_from_hex(&"ab".repeat(31)).is_err());
+        assert!(public_key_from_hex(&"AB".repeat(32)).is_ok());
+    }
+
+    #[test]
+    fn test_chunk_round_trip_through_png() {
+        let (secret, public) = generate_identity();
+        let chunk_type = ChunkType::try_from(RECIPIENT_CHUNK_TYPE).unwrap();
+        let mut png = Png::from_chunks(Vec::new());
+        png.append_chunk(encrypt_chunk(chunk_type, b"meet me at noon", &[public]).unwrap());
+
+        let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        let chunk = png.chunk_by_type("reCp").unwrap();
+
+        assert_eq!(decrypt_chunk(chunk, &secret).unwrap(), b"meet me at noon");
+    }
+}
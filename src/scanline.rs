@@ -0,0 +1,358 @@
+use std::fmt::Display;
+
+use crate::error::{Error, Result};
+use crate::ihdr::{Ihdr, Interlace};
+use crate::zlib;
+
+/// Origin and step of each Adam7 pass, as `(x0, y0, dx, dy)`
+const ADAM7: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// The filter applied to one scanline before compression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterType {
+    None,
+    Sub,
+    Up,
+    Average,
+    Paeth,
+}
+
+impl FilterType {
+    pub const ALL: [FilterType; 5] = [
+        FilterType::None,
+        FilterType::Sub,
+        FilterType::Up,
+        FilterType::Average,
+        FilterType::Paeth,
+    ];
+
+    pub fn from_byte(byte: u8) -> Option<FilterType> {
+        FilterType::ALL.get(byte as usize).copied()
+    }
+
+    pub fn to_byte(self) -> u8 {
+        self as u8
+    }
+}
+
+impl Display for FilterType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let (to_left, to_up, to_up_left) = (
+        (estimate - left as i16).abs(),
+        (estimate - up as i16).abs(),
+        (estimate - up_left as i16).abs(),
+    );
+    if to_left <= to_up && to_left <= to_up_left {
+        left
+    } else if to_up <= to_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+/// Reverses `filter` on one scanline in place. `previous` is the unfiltered
+/// line above (all zeros for the first line of a pass) and `bpp` the number
+/// of bytes per complete pixel, at least 1.
+pub(crate) fn unfilter(filter: FilterType, line: &mut [u8], previous: &[u8], bpp: usize) {
+    match filter {
+        FilterType::None => {}
+        FilterType::Sub => {
+            for index in bpp..line.len() {
+                line[index] = line[index].wrapping_add(line[index - bpp]);
+            }
+        }
+        FilterType::Up => {
+            for (byte, up) in line.iter_mut().zip(previous) {
+                *byte = byte.wrapping_add(*up);
+            }
+        }
+        FilterType::Average => {
+            for index in 0..line.len() {
+                let left = if index >= bpp { line[index - bpp] } else { 0 };
+                let average = ((left as u16 + previous[index] as u16) / 2) as u8;
+                line[index] = line[index].wrapping_add(average);
+            }
+        }
+        FilterType::Paeth => {
+            for index in 0..line.len() {
+                let (left, up_left) = if index >= bpp {
+                    (line[index - bpp], previous[index - bpp])
+                } else {
+                    (0, 0)
+                };
+                line[index] = line[index].wrapping_add(paeth(left, previous[index], up_left));
+            }
+        }
+    }
+}
+
+/// Applies `filter` to one unfiltered `line`, appending the result to `out`.
+/// The inverse of `unfilter`.
+pub(crate) fn filter(
+    filter: FilterType,
+    line: &[u8],
+    previous: &[u8],
+    bpp: usize,
+    out: &mut Vec<u8>,
+) {
+    let left = |index: usize| if index >= bpp { line[index - bpp] } else { 0 };
+    let up_left = |index: usize| if index >= bpp { previous[index - bpp] } else { 0 };
+    out.extend((0..line.len()).map(|index| {
+        let predictor = match filter {
+            FilterType::None => 0,
+            FilterType::Sub => left(index),
+            FilterType::Up => previous[index],
+            FilterType::Average => ((left(index) as u16 + previous[index] as u16) / 2) as u8,
+            FilterType::Paeth => paeth(left(index), previous[index], up_left(index)),
+        };
+        line[index].wrapping_sub(predictor)
+    }));
+}
+
+/// A sub-image stored contiguously in the image data: the whole image when
+/// not interlaced, or one of the seven Adam7 passes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pass {
+    /// 0 for non-interlaced images, 1 to 7 for Adam7 passes
+    pub number: u8,
+    pub width: u32,
+    pub height: u32,
+    x0: u32,
+    y0: u32,
+    dx: u32,
+    dy: u32,
+}
+
+impl Pass {
+    /// Passes in the order their scanlines are stored. Adam7 passes that hold
+    /// no pixels for small images are left out, since they store nothing.
+    pub fn for_image(ihdr: &Ihdr) -> Vec<Pass> {
+        match ihdr.interlace {
+            Interlace::None => vec![Pass {
+                number: 0,
+                width: ihdr.width,
+                height: ihdr.height,
+                x0: 0,
+                y0: 0,
+                dx: 1,
+                dy: 1,
+            }],
+            Interlace::Adam7 => ADAM7
+                .iter()
+                .enumerate()
+                .map(|(index, &(x0, y0, dx, dy))| Pass {
+                    number: index as u8 + 1,
+                    width: ihdr.width.saturating_sub(x0).div_ceil(dx),
+                    height: ihdr.height.saturating_sub(y0).div_ceil(dy),
+                    x0,
+                    y0,
+                    dx,
+                    dy,
+                })
+                .filter(|pass| pass.width > 0 && pass.height > 0)
+                .collect(),
+        }
+    }
+
+    /// Where pixel `(x, y)` of this pass sits in the full image
+    pub fn image_position(&self, x: u32, y: u32) -> (u32, u32) {
+        (self.x0 + x * self.dx, self.y0 + y * self.dy)
+    }
+
+    /// Bytes the pass takes in the image data, filter bytes included
+    fn stored_length(&self, ihdr: &Ihdr) -> Option<usize> {
+        (ihdr.row_bytes(self.width) + 1).checked_mul(self.height as usize)
+    }
+}
+
+/// One stored row, still filtered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scanline<'a> {
+    pub pass: Pass,
+    /// Row within the pass
+    pub row: u32,
+    pub filter: FilterType,
+    pub data: &'a [u8],
+}
+
+/// The inflated contents of all IDAT chunks, still filtered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageData {
+    pub ihdr: Ihdr,
+    pub bytes: Vec<u8>,
+}
+
+fn invalid(reason: &'static str) -> Error {
+    Error::InvalidChunkData {
+        chunk_type: String::from("IDAT"),
+        reason,
+    }
+}
+
+impl ImageData {
+    /// Inflates the concatenated IDAT payloads. The stream must hold exactly
+    /// the bytes the IHDR geometry calls for, and inflating stops as soon as
+    /// it produces more, so a hostile stream cannot run away with memory.
+    pub fn inflate(ihdr: Ihdr, compressed: &[u8]) -> Result<ImageData> {
+        let expected = Pass::for_image(&ihdr)
+            .iter()
+            .try_fold(0usize, |total, pass| total.checked_add(pass.stored_length(&ihdr)?))
+            .ok_or(invalid("image is too large"))?;
+        let bytes = zlib::inflate(compressed, expected).map_err(invalid)?;
+        if bytes.len() < expected {
+            return Err(invalid("image data is shorter than the image"));
+        }
+        Ok(ImageData { ihdr, bytes })
+    }
+
+    /// Every scanline in storage order, with passes one after another
+    pub fn scanlines(&self) -> Result<Vec<Scanline<'_>>> {
+        let mut scanlines = Vec::new();
+        let mut rest = self.bytes.as_slice();
+        for pass in Pass::for_image(&self.ihdr) {
+            let stride = self.ihdr.row_bytes(pass.width) + 1;
+            for row in 0..pass.height {
+                if rest.len() < stride {
+                    return Err(invalid("image data is shorter than the image"));
+                }
+                let (line, after) = rest.split_at(stride);
+                let filter = FilterType::from_byte(line[0]).ok_or(invalid("unknown filter type"))?;
+                scanlines.push(Scanline { pass, row, filter, data: &line[1..] });
+                rest = after;
+            }
+        }
+        Ok(scanlines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::ColorType;
+
+    fn ihdr(width: u32, height: u32, bit_depth: u8, interlace: Interlace) -> Ihdr {
+        Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type: ColorType::Grayscale,
+            compression: 0,
+            filter: 0,
+            interlace,
+        }
+    }
+
+    #[test]
+    fn test_adam7_pass_sizes() {
+        let sizes: Vec<(u8, u32, u32)> = Pass::for_image(&ihdr(8, 8, 8, Interlace::Adam7))
+            .iter()
+            .map(|pass| (pass.number, pass.width, pass.height))
+            .collect();
+        assert_eq!(
+            sizes,
+            vec![(1, 1, 1), (2, 1, 1), (3, 2, 1), (4, 2, 2), (5, 4, 2), (6, 4, 4), (7, 8, 4)]
+        );
+
+        // A 1x1 image only has a pixel in the first pass
+        assert_eq!(Pass::for_image(&ihdr(1, 1, 8, Interlace::Adam7)).len(), 1);
+    }
+
+    #[test]
+    fn test_every_pixel_covered_once() {
+        let (width, height) = (13, 7);
+        let mut seen = vec![0; (width * height) as usize];
+        for pass in Pass::for_image(&ihdr(width, height, 8, Interlace::Adam7)) {
+            for y in 0..pass.height {
+                for x in 0..pass.width {
+                    let (image_x, image_y) = pass.image_position(x, y);
+                    seen[(image_y * width + image_x) as usize] += 1;
+                }
+            }
+        }
+        assert!(seen.iter().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn test_scanlines() {
+        // 3x2, 4-bit grayscale: 2 bytes per row plus the filter byte
+        let raw = [0, 0x12, 0x30, 2, 0x45, 0x60];
+        let header = ihdr(3, 2, 4, Interlace::None);
+        let data = ImageData::inflate(header, &zlib::deflate(&raw)).unwrap();
+        let scanlines = data.scanlines().unwrap();
+
+        assert_eq!(scanlines.len(), 2);
+        assert_eq!(scanlines[0].filter, FilterType::None);
+        assert_eq!(scanlines[0].data, &[0x12, 0x30]);
+        assert_eq!(scanlines[1].filter, FilterType::Up);
+        assert_eq!(scanlines[1].row, 1);
+    }
+
+    #[test]
+    fn test_scanlines_short_data() {
+        // The fields are public, so the bytes need not match the IHDR
+        let header = ihdr(3, 2, 4, Interlace::None);
+        let data = ImageData { ihdr: header, bytes: vec![0, 0x12, 0x30, 2] };
+        assert!(data.scanlines().is_err());
+    }
+
+    #[test]
+    fn test_wrong_length() {
+        let header = ihdr(3, 2, 8, Interlace::None);
+        let short = zlib::deflate(&[0; 7]);
+        let long = zlib::deflate(&[0; 9]);
+        assert!(ImageData::inflate(header, &short).is_err());
+        assert!(ImageData::inflate(header, &long).is_err());
+        assert!(ImageData::inflate(header, &zlib::deflate(&[0; 8])).is_ok());
+    }
+
+    #[test]
+    fn test_unfilter() {
+        let previous = [10, 20, 30, 40];
+        let cases = [
+            (FilterType::None, [1, 2, 3, 4], [1, 2, 3, 4]),
+            (FilterType::Sub, [1, 2, 3, 4], [1, 2, 4, 6]),
+            (FilterType::Up, [1, 2, 3, 4], [11, 22, 33, 44]),
+            (FilterType::Average, [1, 2, 3, 4], [6, 12, 21, 30]),
+            (FilterType::Paeth, [1, 2, 3, 4], [11, 22, 33, 44]),
+        ];
+        for (filter, mut line, expected) in cases {
+            unfilter(filter, &mut line, &previous, 2);
+            assert_eq!(line, expected, "{}", filter);
+        }
+    }
+
+    #[test]
+    fn test_filter_reverses_unfilter() {
+        let previous = [200, 3, 90, 255, 0, 17];
+        let line = [1, 250, 128, 7, 66, 9];
+        for filter_type in FilterType::ALL {
+            let mut filtered = Vec::new();
+            filter(filter_type, &line, &previous, 3, &mut filtered);
+            unfilter(filter_type, &mut filtered, &previous, 3);
+            assert_eq!(filtered, line, "{}", filter_type);
+        }
+    }
+
+    #[test]
+    fn test_unknown_filter_type() {
+        let raw = [5, 0, 0, 0];
+        let header = ihdr(3, 1, 8, Interlace::None);
+        let data = ImageData::inflate(header, &zlib::deflate(&raw)).unwrap();
+        assert!(matches!(data.scanlines(), Err(Error::InvalidChunkData { .. })));
+    }
+}
@@ -0,0 +1,103 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+
+/// Chunks that change how colors are rendered
+pub const COLOR_MANAGEMENT_TYPES: [[u8; 4]; 8] = [
+    *b"gAMA", *b"cHRM", *b"sRGB", *b"iCCP", *b"sBIT", *b"cICP", *b"mDCV", *b"cLLI",
+];
+
+/// Decides which chunks `Png::scrub` keeps. Critical chunks are always kept
+/// since the image cannot be decoded without them, and so is tRNS because it
+/// carries transparency. Every other ancillary chunk goes, unless allowed.
+#[derive(Debug)]
+pub struct ScrubPolicy {
+    /// Keep the `COLOR_MANAGEMENT_TYPES` chunks so colors render the same
+    pub keep_color_management: bool,
+    /// Ancillary chunk types to keep regardless of the rules above
+    pub allow: Vec<ChunkType>,
+    /// Ancillary chunk types to remove even if otherwise kept; wins over `allow`
+    pub deny: Vec<ChunkType>,
+}
+
+impl Default for ScrubPolicy {
+    fn default() -> ScrubPolicy {
+        ScrubPolicy {
+            keep_color_management: true,
+            allow: Vec::new(),
+            deny: Vec::new(),
+        }
+    }
+}
+
+impl ScrubPolicy {
+    pub fn keeps(&self, chunk_type: &ChunkType) -> bool {
+        if chunk_type.is_critical() {
+            return true;
+        }
+        if self.deny.contains(chunk_type) {
+            return false;
+        }
+        let bytes = chunk_type.bytes();
+        self.allow.contains(chunk_type)
+            || &bytes == b"tRNS"
+            || (self.keep_color_management && COLOR_MANAGEMENT_TYPES.contains(&bytes))
+    }
+}
+
+/// What `Png::scrub` removed, in file order
+#[derive(Debug, Default)]
+pub struct ScrubReport {
+    pub removed: Vec<Chunk>,
+}
+
+impl ScrubReport {
+    /// Bytes saved, counting each chunk's length, type and CRC fields too
+    pub fn removed_bytes(&self) -> usize {
+        self.removed.iter().map(|chunk| chunk.length() as usize + 12).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn keeps(policy: &ScrubPolicy, chunk_type: &str) -> bool {
+        policy.keeps(&ChunkType::from_str(chunk_type).unwrap())
+    }
+
+    #[test]
+    fn test_default_policy() {
+        let policy = ScrubPolicy::default();
+        for kept in ["IHDR", "PLTE", "IDAT", "IEND", "tRNS", "gAMA", "iCCP", "sRGB"] {
+            assert!(keeps(&policy, kept), "{}", kept);
+        }
+        for removed in ["tEXt", "zTXt", "iTXt", "eXIf", "tIME", "pHYs", "ruSt", "crYp", "siGN"] {
+            assert!(!keeps(&policy, removed), "{}", removed);
+        }
+    }
+
+    #[test]
+    fn test_without_color_management() {
+        let policy = ScrubPolicy { keep_color_management: false, ..Default::default() };
+        assert!(!keeps(&policy, "gAMA"));
+        assert!(keeps(&policy, "tRNS"));
+    }
+
+    #[test]
+    fn test_allow_and_deny() {
+        let policy = ScrubPolicy {
+            keep_color_management: true,
+            allow: vec![ChunkType::from_str("pHYs").unwrap(), ChunkType::from_str("tIME").unwrap()],
+            deny: vec![
+                ChunkType::from_str("tIME").unwrap(),
+                ChunkType::from_str("iCCP").unwrap(),
+                ChunkType::from_str("IDAT").unwrap(),
+            ],
+        };
+        assert!(keeps(&policy, "pHYs"));
+        assert!(!keeps(&policy, "tIME"));
+        assert!(!keeps(&policy, "iCCP"));
+        assert!(keeps(&policy, "IDAT"));
+    }
+}
@@ -0,0 +1,364 @@
+use std::fmt::Display;
+
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{Error, Result};
+use crate::png::{InsertionPolicy, Png};
+use crate::recipients::{key_from_hex, key_to_hex};
+
+/// Chunk type holding the signature: ancillary, private, and not safe to
+/// copy since any edit to the image invalidates it
+pub const SIGNATURE_CHUNK_TYPE: [u8; 4] = *b"siGN";
+
+const MAGIC: [u8; 4] = *b"sgnt";
+const VERSION: u8 = 1;
+const ED25519: u8 = 1;
+/// Prefixed to the signed bytes so the signature cannot be replayed in
+/// another protocol
+const DOMAIN: &[u8] = b"rust-png-cryptex signature\0";
+const ENTRY_LENGTH: usize = 8;
+
+/// Names one chunk as the `occurrence`-th chunk of its type, counting from 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoveredChunk {
+    pub chunk_type: [u8; 4],
+    pub occurrence: u32,
+}
+
+impl Display for CoveredChunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}[{}]",
+            String::from_utf8_lossy(&self.chunk_type),
+            self.occurrence
+        )
+    }
+}
+
+/// Outcome of a successful `verify`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verification {
+    pub signer: VerifyingKey,
+    pub covered: Vec<CoveredChunk>,
+    /// Chunks present in the file that the signature does not protect,
+    /// not counting the signature chunk itself
+    pub uncovered: Vec<CoveredChunk>,
+}
+
+pub fn generate_signing_key() -> SigningKey {
+    let mut bytes = [0; 32];
+    OsRng.fill_bytes(&mut bytes);
+    SigningKey::from_bytes(&bytes)
+}
+
+pub fn signing_key_from_hex(hex: &str) -> Result<SigningKey> {
+    key_from_hex(hex).map(|bytes| SigningKey::from_bytes(&bytes))
+}
+
+pub fn verifying_key_from_hex(hex: &str) -> Result<VerifyingKey> {
+    VerifyingKey::from_bytes(&key_from_hex(hex)?)
+        .map_err(|_| Error::InvalidKey { reason: "not a valid Ed25519 public key" })
+}
+
+/// Pairs every chunk with its `CoveredChunk` name, in file order
+fn named_chunks(png: &Png) -> Vec<(CoveredChunk, &Chunk)> {
+    let mut seen: Vec<([u8; 4], u32)> = Vec::new();
+    png.chunks()
+        .iter()
+        .map(|chunk| {
+            let chunk_type = chunk.chunk_type().bytes();
+            let occurrence = match seen.iter_mut().find(|(seen_type, _)| *seen_type == chunk_type) {
+                Some((_, count)) => {
+                    *count += 1;
+                    *count
+                }
+                None => {
+                    seen.push((chunk_type, 0));
+                    0
+                }
+            };
+            (CoveredChunk { chunk_type, occurrence }, chunk)
+        })
+        .collect()
+}
+
+/// The default selection: every critical chunk, every private ancillary
+/// chunk, which is where messages go (crYp, reCp, ruSt, ...), and every
+/// chunk whose type is listed in `message_types`
+pub fn default_coverage(png: &Png, message_types: &[&str]) -> Vec<CoveredChunk> {
+    named_chunks(png)
+        .into_iter()
+        .filter(|(_, chunk)| {
+            let chunk_type = chunk.chunk_type();
+            chunk_type.is_critical()
+                || !chunk_type.is_public()
+                || message_types.contains(&chunk_type.to_string().as_str())
+        })
+        .map(|(name, _)| name)
+        .filter(|name| name.chunk_type != SIGNATURE_CHUNK_TYPE)
+        .collect()
+}
+
+/// Builds the bytes that get signed: a domain separator, the signature
+/// chunk's own header, then each covered chunk exactly as `Chunk::as_bytes`
+/// serializes it
+fn signed_message(png: &Png, header: &[u8], covered: &[CoveredChunk]) -> Result<Vec<u8>> {
+    let chunks = named_chunks(png);
+    let mut message = [DOMAIN, header].concat();
+    for name in covered {
+        let chunk = chunks
+            .iter()
+            .find(|(candidate, _)| candidate == name)
+            .map(|(_, chunk)| chunk)
+            .ok_or_else(|| Error::ChunkNotFound { chunk_type: name.to_string() })?;
+        message.extend_from_slice(&chunk.as_bytes());
+    }
+    Ok(message)
+}
+
+/// Signs the `covered` chunks of `png` and stores the result in a signature
+/// chunk just before IEND, replacing any earlier signature. On error `png`
+/// is left as it was.
+pub fn sign(png: &mut Png, key: &SigningKey, covered: &[CoveredChunk]) -> Result<()> {
+    let signature_type = ChunkType::try_from(SIGNATURE_CHUNK_TYPE)?;
+    if covered.len() > u16::MAX as usize {
+        return Err(Error::InvalidPayload { reason: "too many chunks to sign" });
+    }
+    // The old signature is about to be replaced, so it cannot be covered
+    if covered.iter().any(|name| name.chunk_type == SIGNATURE_CHUNK_TYPE) {
+        return Err(Error::InvalidPayload { reason: "the signature chunk cannot sign itself" });
+    }
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&MAGIC);
+    data.push(VERSION);
+    data.push(ED25519);
+    data.extend_from_slice(key.verifying_key().as_bytes());
+    data.extend_from_slice(&(covered.len() as u16).to_be_bytes());
+    for name in covered {
+        data.extend_from_slice(&name.chunk_type);
+        data.extend_from_slice(&name.occurrence.to_be_bytes());
+    }
+
+    let signature = key.sign(&signed_message(png, &data, covered)?);
+    data.extend_from_slice(&signature.to_bytes());
+
+    while png.remove_chunk(&signature_type.to_string()).is_ok() {}
+    png.insert_chunk(Chunk::new(signature_type, data), InsertionPolicy::BeforeIend);
+    Ok(())
+}
+
+/// Checks that the signature chunk of `png` was made by `trusted` and
+/// reports which chunks it covers. Chunks that are not listed are not
+/// protected. The key stored in the chunk is never trusted on its own, since
+/// anyone can re-sign a file with a key of their own.
+pub fn verify(png: &Png, trusted: &VerifyingKey) -> Result<Verification> {
+    let signature_type = String::from_utf8_lossy(&SIGNATURE_CHUNK_TYPE).into_owned();
+    let chunk = png
+        .chunk_by_type(&signature_type)
+        .ok_or(Error::ChunkNotFound { chunk_type: signature_type })?;
+    let data = chunk.data();
+
+    let fixed_length = MAGIC.len() + 2 + 32 + 2;
+    if data.len() < fixed_length || !data.starts_with(&MAGIC) {
+        return Err(Error::InvalidPayload { reason: "malformed signature chunk" });
+    }
+    if data[4] != VERSION {
+        return Err(Error::UnsupportedVersion { version: data[4] });
+    }
+    if data[5] != ED25519 {
+        return Err(Error::UnsupportedAlgorithm { algorithm: data[5] });
+    }
+
+    let signer = VerifyingKey::from_bytes(&data[6..38].try_into().unwrap())
+        .map_err(|_| Error::InvalidKey { reason: "not a valid Ed25519 public key" })?;
+    let count = u16::from_be_bytes([data[38], data[39]]) as usize;
+    let header_length = fixed_length + count * ENTRY_LENGTH;
+    if data.len() != header_length + Signature::BYTE_SIZE {
+        return Err(Error::InvalidPayload { reason: "malformed signature chunk" });
+    }
+
+    let (header, signature) = data.split_at(header_length);
+    let covered: Vec<CoveredChunk> = header[fixed_length..]
+        .chunks_exact(ENTRY_LENGTH)
+        .map(|entry| CoveredChunk {
+            chunk_type: entry[..4].try_into().unwrap(),
+            occurrence: u32::from_be_bytes(entry[4..].try_into().unwrap()),
+        })
+        .collect();
+
+    let signature = Signature::from_bytes(&signature.try_into().unwrap());
+    let message = signed_message(png, header, &covered).map_err(|_| Error::SignatureMismatch)?;
+    signer
+        .verify_strict(&message, &signature)
+        .map_err(|_| Error::SignatureMismatch)?;
+    if signer != *trusted {
+        return Err(Error::UntrustedSigner { signer: key_to_hex(signer.as_bytes()) });
+    }
+
+    let uncovered = named_chunks(png)
+        .into_iter()
+        .map(|(name, _)| name)
+        .filter(|name| name.chunk_type != SIGNATURE_CHUNK_TYPE && !covered.contains(name))
+        .collect();
+    Ok(Verification { signer, covered, uncovered })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.as_bytes().to_vec())
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", "header"),
+            chunk("IDAT", "first"),
+            chunk("IDAT", "second"),
+            chunk("tEXt", "Comment\0unsigned"),
+            chunk("ruSt", "the message"),
+            chunk("IEND", ""),
+        ])
+    }
+
+    #[test]
+    fn test_default_coverage() {
+        let png = testing_png();
+        let names = |covered: Vec<CoveredChunk>| -> Vec<String> {
+            covered.iter().map(|name| name.to_string()).collect()
+        };
+        // The private ruSt message chunk is covered without being asked for
+        assert_eq!(
+            names(default_coverage(&png, &[])),
+            vec!["IHDR[0]", "IDAT[0]", "IDAT[1]", "ruSt[0]", "IEND[0]"]
+        );
+        assert_eq!(
+            names(default_coverage(&png, &["tEXt"])),
+            vec!["IHDR[0]", "IDAT[0]", "IDAT[1]", "tEXt[0]", "ruSt[0]", "IEND[0]"]
+        );
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = generate_signing_key();
+        let mut png = testing_png();
+        let covered = default_coverage(&png, &["ruSt"]);
+
+        sign(&mut png, &key, &covered).unwrap();
+        let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        let verification = verify(&png, &key.verifying_key()).unwrap();
+
+        assert_eq!(verification.signer, key.verifying_key());
+        assert_eq!(verification.covered, covered);
+        assert_eq!(png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+    }
+
+    #[test]
+    fn test_modified_chunk_fails() {
+        let key = generate_signing_key();
+        let mut png = testing_png();
+        let covered = default_coverage(&png, &["ruSt"]);
+        sign(&mut png, &key, &covered).unwrap();
+
+        png.remove_chunk("ruSt").unwrap();
+        png.append_chunk(chunk("ruSt", "a forged message"));
+
+        assert_eq!(verify(&png, &key.verifying_key()).unwrap_err(), Error::SignatureMismatch);
+    }
+
+    #[test]
+    fn test_uncovered_chunk_may_change() {
+        let key = generate_signing_key();
+        let mut png = testing_png();
+        let covered = default_coverage(&png, &["ruSt"]);
+        sign(&mut png, &key, &covered).unwrap();
+
+        png.remove_chunk("tEXt").unwrap();
+        png.append_chunk(chunk("tEXt", "Comment\0added later"));
+
+        let verification = verify(&png, &key.verifying_key()).unwrap();
+        let uncovered: Vec<String> = verification
+            .uncovered
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        assert_eq!(uncovered, vec!["tEXt[0]"]);
+    }
+
+    #[test]
+    fn test_resigning_replaces_signature() {
+        let key = generate_signing_key();
+        let mut png = testing_png();
+        let covered = default_coverage(&png, &[]);
+        sign(&mut png, &key, &covered).unwrap();
+        sign(&mut png, &key, &covered).unwrap();
+
+        let signatures = png
+            .chunks()
+            .iter()
+            .filter(|chunk| chunk.chunk_type().bytes() == SIGNATURE_CHUNK_TYPE)
+            .count();
+        assert_eq!(signatures, 1);
+        assert!(verify(&png, &key.verifying_key()).is_ok());
+    }
+
+    #[test]
+    fn test_other_signer_is_untrusted() {
+        let key = generate_signing_key();
+        let mut png = testing_png();
+        let covered = default_coverage(&png, &["ruSt"]);
+        sign(&mut png, &key, &covered).unwrap();
+
+        // Re-signing with another key gives a valid signature by that key
+        let forger = generate_signing_key();
+        sign(&mut png, &forger, &covered).unwrap();
+        assert!(verify(&png, &forger.verifying_key()).is_ok());
+        assert_eq!(
+            verify(&png, &key.verifying_key()),
+            Err(Error::UntrustedSigner {
+                signer: key_to_hex(forger.verifying_key().as_bytes())
+            })
+        );
+    }
+
+    #[test]
+    fn test_failed_sign_leaves_png_unchanged() {
+        let key = generate_signing_key();
+        let mut png = testing_png();
+        let covered = default_coverage(&png, &[]);
+        sign(&mut png, &key, &covered).unwrap();
+        let before = png.as_bytes();
+
+        let missing = CoveredChunk { chunk_type: *b"zzZz", occurrence: 0 };
+        assert!(sign(&mut png, &key, &[missing]).is_err());
+        let itself = CoveredChunk { chunk_type: SIGNATURE_CHUNK_TYPE, occurrence: 0 };
+        assert!(sign(&mut png, &key, &[itself]).is_err());
+
+        assert_eq!(png.as_bytes(), before);
+        assert!(verify(&png, &key.verifying_key()).is_ok());
+    }
+
+    #[test]
+    fn test_unsigned_png() {
+        let key = generate_signing_key();
+        assert!(matches!(
+            verify(&testing_png(), &key.verifying_key()),
+            Err(Error::ChunkNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_hex_keys() {
+        let key = generate_signing_key();
+        let hex = crate::recipients::key_to_hex(key.verifying_key().as_bytes());
+        assert_eq!(verifying_key_from_hex(&hex).unwrap(), key.verifying_key());
+    }
+}
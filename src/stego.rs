@@ -0,0 +1,470 @@
+use sha2::{Digest, Sha256};
+
+use crate::crypto::Kdf;
+use crate::encoder::EncoderOptions;
+use crate::error::{Error, Result};
+use crate::image::Image;
+use crate::png::Png;
+
+/// Marks the start of every message hidden in the pixels
+const MAGIC: [u8; 4] = *b"lsbx";
+const VERSION: u8 = 1;
+/// Magic, version, message length and the message's CRC-32
+const HEADER_LENGTH: usize = MAGIC.len() + 1 + 4 + 4;
+
+const CRC: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+/// Fixed salt for stretching ordering passphrases. There is nowhere to keep
+/// a random one without giving away that the image holds a message.
+const KEY_SALT: &[u8] = b"png-cryptex lsb ordering";
+const FEISTEL_ROUNDS: u8 = 8;
+
+/// Secret that decides the order in which samples carry message bits
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct LsbKey([u8; 32]);
+
+impl LsbKey {
+    pub fn from_bytes(bytes: [u8; 32]) -> LsbKey {
+        LsbKey(bytes)
+    }
+
+    /// Stretches `passphrase` with Argon2id at the default cost
+    pub fn from_passphrase(passphrase: &str) -> Result<LsbKey> {
+        Ok(LsbKey(Kdf::default().derive_key(passphrase, KEY_SALT)?))
+    }
+}
+
+impl std::fmt::Debug for LsbKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LsbKey(..)")
+    }
+}
+
+/// A keyed pseudo-random permutation of `0..size`: a balanced Feistel
+/// network over the smallest even number of bits that covers `size`, with
+/// SHA-256 as the round function. Outputs past `size` are fed back in
+/// (cycle walking) until they land inside, which takes fewer than four
+/// steps on average and needs no memory however large the image is.
+#[derive(Debug, Clone, Copy)]
+struct Permutation {
+    key: LsbKey,
+    size: u64,
+    half_bits: u32,
+}
+
+impl Permutation {
+    fn new(key: LsbKey, size: usize) -> Permutation {
+        let bits = u64::BITS - (size as u64).saturating_sub(1).leading_zeros();
+        Permutation {
+            key,
+            size: size as u64,
+            half_bits: bits.div_ceil(2).max(1),
+        }
+    }
+
+    fn round(&self, round: u8, value: u64) -> u64 {
+        let digest = Sha256::new()
+            .chain_update(self.key.0)
+            .chain_update([round])
+            .chain_update(value.to_be_bytes())
+            .finalize();
+        u64::from_be_bytes(digest[..8].try_into().unwrap())
+    }
+
+    fn feistel(&self, value: u64) -> u64 {
+        let mask = (1 << self.half_bits) - 1;
+        let (mut left, mut right) = (value >> self.half_bits, value & mask);
+        for round in 0..FEISTEL_ROUNDS {
+            (left, right) = (right, left ^ (self.round(round, right) & mask));
+        }
+        (left << self.half_bits) | right
+    }
+
+    fn apply(&self, value: usize) -> usize {
+        let mut value = self.feistel(value as u64);
+        while value >= self.size {
+            value = self.feistel(value);
+        }
+        value as usize
+    }
+}
+
+/// Which bits of which samples carry the message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LsbOptions {
+    /// Low bits used in each selected sample, from 1 up to the bit depth
+    pub bits_per_channel: u8,
+    /// Bit `n` selects channel `n` of the image's layout, e.g. `0b0111` for
+    /// red, green and blue. `None` selects every channel except alpha.
+    pub channel_mask: Option<u8>,
+    /// Scatter the message over the samples in an order only the key
+    /// holder can reproduce; `None` fills samples in raster order
+    pub key: Option<LsbKey>,
+}
+
+impl Default for LsbOptions {
+    fn default() -> LsbOptions {
+        LsbOptions {
+            bits_per_channel: 1,
+            channel_mask: None,
+            key: None,
+        }
+    }
+}
+
+/// The samples of one image that can carry message bits, in embedding order
+struct Carrier {
+    /// Indices of the selected channels within a pixel
+    channels: Vec<usize>,
+    bits: usize,
+    pixel_channels: usize,
+    samples: usize,
+    order: Option<Permutation>,
+}
+
+impl Carrier {
+    fn new(image: &Image, options: &LsbOptions) -> Result<Carrier> {
+        let layout = image.layout;
+        if options.bits_per_channel == 0 || options.bits_per_channel > image.bit_depth {
+            return Err(Error::InvalidPayload {
+                reason: "bits per channel must be between 1 and the bit depth",
+            });
+        }
+        let channels: Vec<usize> = (0..layout.channels())
+            .filter(|&channel| match options.channel_mask {
+                Some(mask) => mask & (1 << channel) != 0,
+                None => !(layout.has_alpha() && channel == layout.channels() - 1),
+            })
+            .collect();
+        if channels.is_empty() {
+            return Err(Error::InvalidPayload { reason: "no channel of the image is selected" });
+        }
+        let samples = image.width as usize * image.height as usize * channels.len();
+        Ok(Carrier {
+            samples,
+            order: options.key.map(|key| Permutation::new(key, samples)),
+            channels,
+            bits: options.bits_per_channel as usize,
+            pixel_channels: layout.channels(),
+        })
+    }
+
+    fn capacity_bits(&self) -> usize {
+        self.samples * self.bits
+    }
+
+    /// The sample holding message bit `position`, as an index into the
+    /// image's samples, and which bit of that sample it is
+    fn locate(&self, position: usize) -> (usize, usize) {
+        let ordinal = match &self.order {
+            Some(order) => order.apply(position / self.bits),
+            None => position / self.bits,
+        };
+        let pixel = ordinal / self.channels.len();
+        let channel = self.channels[ordinal % self.channels.len()];
+        (pixel * self.pixel_channels + channel, position % self.bits)
+    }
+}
+
+fn read_sample(image: &Image, index: usize) -> u16 {
+    if image.bit_depth == 16 {
+        u16::from_be_bytes([image.data[2 * index], image.data[2 * index + 1]])
+    } else {
+        image.data[index] as u16
+    }
+}
+
+fn write_sample(image: &mut Image, index: usize, value: u16) {
+    if image.bit_depth == 16 {
+        image.data[2 * index..2 * index + 2].copy_from_slice(&value.to_be_bytes());
+    } else {
+        image.data[index] = value as u8;
+    }
+}
+
+/// Reads `length` bytes starting at message bit `start`, most significant
+/// bit first
+fn read_bytes(image: &Image, carrier: &Carrier, start: usize, length: usize) -> Vec<u8> {
+    (0..length)
+        .map(|byte| {
+            (0..8).fold(0u8, |value, bit| {
+                let (index, plane) = carrier.locate(start + byte * 8 + bit);
+                (value << 1) | ((read_sample(image, index) >> plane) & 1) as u8
+            })
+        })
+        .collect()
+}
+
+/// Bytes of message `image` can hold with these options, after the header
+pub fn capacity(image: &Image, options: &LsbOptions) -> Result<usize> {
+    let carrier = Carrier::new(image, options)?;
+    Ok((carrier.capacity_bits() / 8).saturating_sub(HEADER_LENGTH))
+}
+
+/// Writes `message` into the low bits of the selected samples, behind a
+/// header giving its length and checksum. Samples the message does not
+/// reach are left untouched.
+pub fn embed(image: &mut Image, message: &[u8], options: &LsbOptions) -> Result<()> {
+    let carrier = Carrier::new(image, options)?;
+    if image.data.len() != image.pixel_bytes() * image.width as usize * image.height as usize {
+        return Err(Error::InvalidPayload { reason: "pixel data does not match the image size" });
+    }
+    if message.len() > (carrier.capacity_bits() / 8).saturating_sub(HEADER_LENGTH) {
+        return Err(Error::InvalidPayload { reason: "message does not fit in the image" });
+    }
+
+    let mut stream = Vec::with_capacity(HEADER_LENGTH + message.len());
+    stream.extend_from_slice(&MAGIC);
+    stream.push(VERSION);
+    stream.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    stream.extend_from_slice(&CRC.checksum(message).to_be_bytes());
+    stream.extend_from_slice(message);
+
+    for (byte_index, byte) in stream.iter().enumerate() {
+        for bit in 0..8 {
+            let (index, plane) = carrier.locate(byte_index * 8 + bit);
+            let value = ((byte >> (7 - bit)) & 1) as u16;
+            let sample = read_sample(image, index) & !(1 << plane) | (value << plane);
+            write_sample(image, index, sample);
+        }
+    }
+    Ok(())
+}
+
+/// Recovers a message written by `embed` with the same options. With a
+/// different key the header reads as noise, so this fails with "no hidden
+/// message found" rather than returning garbage.
+pub fn extract(image: &Image, options: &LsbOptions) -> Result<Vec<u8>> {
+    let carrier = Carrier::new(image, options)?;
+    let capacity = (carrier.capacity_bits() / 8).saturating_sub(HEADER_LENGTH);
+    if carrier.capacity_bits() < HEADER_LENGTH * 8 {
+        return Err(Error::InvalidPayload { reason: "the image is too small to hold a message" });
+    }
+
+    let header = read_bytes(image, &carrier, 0, HEADER_LENGTH);
+    if header[..MAGIC.len()] != MAGIC {
+        return Err(Error::InvalidPayload { reason: "no hidden message found" });
+    }
+    if header[4] != VERSION {
+        return Err(Error::UnsupportedVersion { version: header[4] });
+    }
+    let length = u32::from_be_bytes(header[5..9].try_into().unwrap()) as usize;
+    let checksum = u32::from_be_bytes(header[9..13].try_into().unwrap());
+    if length > capacity {
+        return Err(Error::InvalidPayload { reason: "hidden message is longer than the image" });
+    }
+
+    let message = read_bytes(image, &carrier, HEADER_LENGTH * 8, length);
+    if CRC.checksum(&message) != checksum {
+        return Err(Error::InvalidPayload { reason: "hidden message is corrupt" });
+    }
+    Ok(message)
+}
+
+/// Decodes the pixels of `png`, embeds `message` and stores the result back
+/// losslessly, keeping the interlace method. Indexed images come back as
+/// 8-bit RGB or RGBA, since changing palette entries would not be lossless.
+pub fn hide(png: &mut Png, message: &[u8], options: &LsbOptions) -> Result<()> {
+    let mut image = png.decode_image()?;
+    embed(&mut image, message, options)?;
+    let encoder_options = EncoderOptions {
+        interlace: png.ihdr()?.interlace,
+        ..Default::default()
+    };
+    png.set_image(&image, &encoder_options)
+}
+
+/// Decodes the pixels of `png` and extracts a message written by `hide`
+pub fn reveal(png: &Png, options: &LsbOptions) -> Result<Vec<u8>> {
+    extract(&png.decode_image()?, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder;
+    use crate::image::ChannelLayout;
+    use crate::png::tests::PNG_FILE;
+
+    fn noise(width: u32, height: u32, layout: ChannelLayout, bit_depth: u8) -> Image {
+        let mut image = Image::new(width, height, layout, bit_depth);
+        let max = if bit_depth == 16 { u16::MAX as u32 } else { (1 << bit_depth) - 1 };
+        let mut state = 0x2545_f491u32;
+        for y in 0..height {
+            for x in 0..width {
+                for channel in 0..layout.channels() {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    image.set_sample(x, y, channel, (state % (max + 1)) as u16);
+                }
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let cases = [
+            (ChannelLayout::Gray, 1, 1),
+            (ChannelLayout::Gray, 4, 3),
+            (ChannelLayout::Gray, 8, 2),
+            (ChannelLayout::GrayAlpha, 16, 5),
+            (ChannelLayout::Rgb, 8, 1),
+            (ChannelLayout::Rgba, 8, 4),
+            (ChannelLayout::Rgba, 16, 16),
+        ];
+        for (layout, bit_depth, bits_per_channel) in cases {
+            let mut image = noise(40, 30, layout, bit_depth);
+            let options = LsbOptions { bits_per_channel, ..Default::default() };
+            let message = vec![0xa5; capacity(&image, &options).unwrap()];
+            embed(&mut image, &message, &options).unwrap();
+            assert_eq!(extract(&image, &options).unwrap(), message, "{:?} {}", layout, bit_depth);
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        let image = Image::new(10, 10, ChannelLayout::Rgba, 8);
+        // 100 pixels, 3 channels without alpha, 1 bit each
+        assert_eq!(capacity(&image, &LsbOptions::default()).unwrap(), 300 / 8 - HEADER_LENGTH);
+        let options = LsbOptions { bits_per_channel: 2, channel_mask: Some(0b1111), key: None };
+        assert_eq!(capacity(&image, &options).unwrap(), 800 / 8 - HEADER_LENGTH);
+
+        let tiny = Image::new(2, 2, ChannelLayout::Gray, 8);
+        assert_eq!(capacity(&tiny, &LsbOptions::default()).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_only_low_bits_of_selected_channels_change() {
+        let original = noise(32, 32, ChannelLayout::Rgba, 8);
+        let mut image = original.clone();
+        let options = LsbOptions { bits_per_channel: 2, channel_mask: Some(0b0101), key: None };
+        embed(&mut image, &[0xff; 100], &options).unwrap();
+        for (index, (&before, &after)) in original.data.iter().zip(&image.data).enumerate() {
+            match index % 4 {
+                0 | 2 => assert_eq!(before & !0b11, after & !0b11),
+                _ => assert_eq!(before, after),
+            }
+        }
+        assert_ne!(original, image);
+    }
+
+    #[test]
+    fn test_message_too_long() {
+        let mut image = Image::new(10, 10, ChannelLayout::Rgb, 8);
+        let options = LsbOptions::default();
+        let too_long = vec![0; capacity(&image, &options).unwrap() + 1];
+        assert!(matches!(
+            embed(&mut image, &too_long, &options),
+            Err(Error::InvalidPayload { .. })
+        ));
+    }
+
+    #[test]
+    fn test_no_message() {
+        let image = noise(20, 20, ChannelLayout::Rgb, 8);
+        assert!(matches!(
+            extract(&image, &LsbOptions::default()),
+            Err(Error::InvalidPayload { reason: "no hidden message found" })
+        ));
+    }
+
+    #[test]
+    fn test_corrupt_message() {
+        let mut image = noise(20, 20, ChannelLayout::Rgb, 8);
+        embed(&mut image, b"hello", &LsbOptions::default()).unwrap();
+        // The first message bit sits right after the header
+        image.data[HEADER_LENGTH * 8] ^= 1;
+        assert!(extract(&image, &LsbOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let mut image = Image::new(10, 10, ChannelLayout::Gray, 4);
+        let too_many = LsbOptions { bits_per_channel: 5, ..Default::default() };
+        assert!(embed(&mut image, b"", &too_many).is_err());
+        let zero = LsbOptions { bits_per_channel: 0, ..Default::default() };
+        assert!(capacity(&image, &zero).is_err());
+        let alpha_only = LsbOptions { channel_mask: Some(0b10), ..Default::default() };
+        assert!(capacity(&image, &alpha_only).is_err());
+    }
+
+    fn keyed(byte: u8) -> LsbOptions {
+        LsbOptions { key: Some(LsbKey::from_bytes([byte; 32])), ..Default::default() }
+    }
+
+    #[test]
+    fn test_permutation_is_a_bijection() {
+        for size in [1, 2, 3, 7, 64, 1000, 4097] {
+            let order = Permutation::new(LsbKey::from_bytes([7; 32]), size);
+            let mut seen = vec![false; size];
+            for value in 0..size {
+                let permuted = order.apply(value);
+                assert!(!seen[permuted], "{} repeats for size {}", permuted, size);
+                seen[permuted] = true;
+            }
+        }
+    }
+
+    #[test]
+    fn test_keyed_round_trip() {
+        let mut image = noise(40, 30, ChannelLayout::Rgb, 8);
+        let message = b"scattered across the image";
+        embed(&mut image, message, &keyed(1)).unwrap();
+        assert_eq!(extract(&image, &keyed(1)).unwrap(), message);
+
+        // The same message written in order touches a prefix of the samples,
+        // while the keyed one reaches past it
+        let original = noise(40, 30, ChannelLayout::Rgb, 8);
+        let used = (HEADER_LENGTH + message.len()) * 8;
+        assert!(original.data[used..] != image.data[used..]);
+    }
+
+    #[test]
+    fn test_wrong_key_fails() {
+        let mut image = noise(40, 30, ChannelLayout::Rgb, 8);
+        embed(&mut image, b"for your eyes only", &keyed(1)).unwrap();
+        for options in [keyed(2), LsbOptions::default()] {
+            assert!(matches!(
+                extract(&image, &options),
+                Err(Error::InvalidPayload { reason: "no hidden message found" })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_key_from_passphrase() {
+        let key = LsbKey::from_passphrase("correct horse").unwrap();
+        assert_eq!(key, LsbKey::from_passphrase("correct horse").unwrap());
+        assert_ne!(key, LsbKey::from_passphrase("battery staple").unwrap());
+        assert_eq!(format!("{:?}", key), "LsbKey(..)");
+    }
+
+    #[test]
+    fn test_hide_in_png() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let before = png.decode_image().unwrap();
+        hide(&mut png, b"hidden in plain sight", &LsbOptions::default()).unwrap();
+
+        let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        assert_eq!(reveal(&png, &LsbOptions::default()).unwrap(), b"hidden in plain sight");
+        assert!(png.chunk_by_type("RuSt").is_some());
+        assert!(png.chunk_by_type("gAMA").is_some());
+        let after = png.decode_image().unwrap();
+        assert!(before.data.iter().zip(&after.data).all(|(a, b)| a >> 1 == b >> 1));
+    }
+
+    #[test]
+    fn test_hide_in_interlaced_png() {
+        let image = noise(33, 17, ChannelLayout::Gray, 16);
+        let options = EncoderOptions {
+            interlace: crate::ihdr::Interlace::Adam7,
+            ..Default::default()
+        };
+        let mut png = encoder::encode(&image, &options).unwrap();
+        hide(&mut png, b"sixteen bits", &LsbOptions::default()).unwrap();
+        assert_eq!(png.ihdr().unwrap().interlace, crate::ihdr::Interlace::Adam7);
+        assert_eq!(reveal(&png, &LsbOptions::default()).unwrap(), b"sixteen bits");
+    }
+}
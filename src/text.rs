@@ -0,0 +1,302 @@
+use std::fmt::Display;
+
+use crate::ancillary::{latin1_to_string, string_to_latin1, validate_keyword};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{Error, Result};
+use crate::zlib;
+
+/// Compressed text is not inflated past this size
+const MAX_TEXT_LENGTH: usize = 1 << 24;
+
+/// Which of the three text chunk types an entry is stored in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextKind {
+    /// tEXt: uncompressed Latin-1
+    Text,
+    /// zTXt: zlib-compressed Latin-1
+    Ztxt,
+    /// iTXt: UTF-8, optionally compressed, with a language tag and the
+    /// keyword translated into that language
+    Itxt {
+        compressed: bool,
+        language_tag: String,
+        translated_keyword: String,
+    },
+}
+
+/// One keyword/value pair from a tEXt, zTXt or iTXt chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEntry {
+    pub keyword: String,
+    pub text: String,
+    pub kind: TextKind,
+}
+
+impl TextEntry {
+    /// Builds an entry in the plainest chunk that can hold `text`: tEXt or
+    /// zTXt when it is Latin-1, iTXt otherwise
+    pub fn new(keyword: &str, text: &str, compressed: bool) -> TextEntry {
+        let kind = match (string_to_latin1(text).is_some(), compressed) {
+            (true, false) => TextKind::Text,
+            (true, true) => TextKind::Ztxt,
+            (false, compressed) => TextKind::Itxt {
+                compressed,
+                language_tag: String::new(),
+                translated_keyword: String::new(),
+            },
+        };
+        TextEntry {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+            kind,
+        }
+    }
+
+    /// Returns true if `chunk_type` is tEXt, zTXt or iTXt
+    pub fn is_text_chunk(chunk_type: &ChunkType) -> bool {
+        matches!(&chunk_type.bytes(), b"tEXt" | b"zTXt" | b"iTXt")
+    }
+
+    pub fn chunk_type(&self) -> [u8; 4] {
+        match self.kind {
+            TextKind::Text => *b"tEXt",
+            TextKind::Ztxt => *b"zTXt",
+            TextKind::Itxt { .. } => *b"iTXt",
+        }
+    }
+
+    /// Serializes the entry, failing if the keyword breaks the spec's rules
+    /// or the text does not fit the chunk type
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        let chunk_type = String::from_utf8_lossy(&self.chunk_type()).into_owned();
+        let invalid = |reason| Error::InvalidChunkData {
+            chunk_type: chunk_type.clone(),
+            reason,
+        };
+
+        let mut data = string_to_latin1(&self.keyword)
+            .ok_or(invalid("keyword must only contain printable Latin-1 characters"))?;
+        validate_keyword(&data).map_err(invalid)?;
+        data.push(0);
+
+        match &self.kind {
+            TextKind::Text | TextKind::Ztxt => {
+                let text = string_to_latin1(&self.text)
+                    .ok_or(invalid("text is not Latin-1, store it in iTXt instead"))?;
+                if text.contains(&0) {
+                    return Err(invalid("text must not contain null characters"));
+                }
+                if self.kind == TextKind::Text {
+                    data.extend_from_slice(&text);
+                } else {
+                    data.push(0);
+                    data.extend_from_slice(&zlib::deflate(&text));
+                }
+            }
+            TextKind::Itxt { compressed, language_tag, translated_keyword } => {
+                if !is_language_tag(language_tag) {
+                    return Err(invalid("language tag must be ASCII letters, digits and hyphens"));
+                }
+                if translated_keyword.contains('\0') {
+                    return Err(invalid("translated keyword must not contain null characters"));
+                }
+                data.extend_from_slice(&[*compressed as u8, 0]);
+                data.extend_from_slice(language_tag.as_bytes());
+                data.push(0);
+                data.extend_from_slice(translated_keyword.as_bytes());
+                data.push(0);
+                if *compressed {
+                    data.extend_from_slice(&zlib::deflate(self.text.as_bytes()));
+                } else {
+                    data.extend_from_slice(self.text.as_bytes());
+                }
+            }
+        }
+
+        Chunk::try_new(ChunkType::try_from(self.chunk_type())?, data)
+    }
+}
+
+fn is_language_tag(tag: &str) -> bool {
+    tag.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'-')
+}
+
+/// The raw keyword of a text chunk, without decompressing anything
+pub(crate) fn raw_keyword(chunk: &Chunk) -> Option<&[u8]> {
+    if !TextEntry::is_text_chunk(chunk.chunk_type()) {
+        return None;
+    }
+    let data = chunk.data();
+    data.iter().position(|&byte| byte == 0).map(|end| &data[..end])
+}
+
+/// Splits `data` at its first null byte
+fn split_null(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let end = data.iter().position(|&byte| byte == 0)?;
+    Some((&data[..end], &data[end + 1..]))
+}
+
+impl TryFrom<&Chunk> for TextEntry {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<TextEntry> {
+        if !TextEntry::is_text_chunk(chunk.chunk_type()) {
+            return Err(Error::InvalidChunkType {
+                chunk_type: chunk.chunk_type().to_string(),
+                offset: 0,
+            });
+        }
+        let chunk_type = chunk.chunk_type().to_string();
+        let invalid = |reason| Error::InvalidChunkData {
+            chunk_type: chunk_type.clone(),
+            reason,
+        };
+
+        let (keyword, rest) =
+            split_null(chunk.data()).ok_or(invalid("keyword is not null terminated"))?;
+        validate_keyword(keyword).map_err(invalid)?;
+        let keyword = latin1_to_string(keyword);
+
+        let (text, kind) = match &chunk.chunk_type().bytes() {
+            b"tEXt" => (latin1_to_string(rest), TextKind::Text),
+            b"zTXt" => {
+                let (method, compressed) =
+                    rest.split_first().ok_or(invalid("compression method is missing"))?;
+                if *method != 0 {
+                    return Err(invalid("unknown compression method"));
+                }
+                let text = zlib::inflate(compressed, MAX_TEXT_LENGTH).map_err(invalid)?;
+                (latin1_to_string(&text), TextKind::Ztxt)
+            }
+            _ => {
+                let (flags, rest) = rest
+                    .split_at_checked(2)
+                    .ok_or(invalid("compression flag and method are missing"))?;
+                let compressed = match flags {
+                    [0, _] => false,
+                    [1, 0] => true,
+                    [1, _] => return Err(invalid("unknown compression method")),
+                    _ => return Err(invalid("compression flag must be 0 or 1")),
+                };
+                let (language_tag, rest) =
+                    split_null(rest).ok_or(invalid("language tag is not null terminated"))?;
+                let (translated_keyword, text) = split_null(rest)
+                    .ok_or(invalid("translated keyword is not null terminated"))?;
+
+                let text = if compressed {
+                    zlib::inflate(text, MAX_TEXT_LENGTH).map_err(invalid)?
+                } else {
+                    text.to_vec()
+                };
+                let utf8 = |bytes: Vec<u8>| {
+                    String::from_utf8(bytes).map_err(|_| invalid("text is not valid UTF-8"))
+                };
+                let language_tag = utf8(language_tag.to_vec())?;
+                if !is_language_tag(&language_tag) {
+                    return Err(invalid("language tag must be ASCII letters, digits and hyphens"));
+                }
+                let kind = TextKind::Itxt {
+                    compressed,
+                    language_tag,
+                    translated_keyword: utf8(translated_keyword.to_vec())?,
+                };
+                (utf8(text)?, kind)
+            }
+        };
+
+        Ok(TextEntry { keyword, text, kind })
+    }
+}
+
+impl Display for TextEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.keyword, self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn round_trip(entry: &TextEntry) -> TextEntry {
+        TextEntry::try_from(&entry.to_chunk().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_new_picks_chunk_type() {
+        assert_eq!(TextEntry::new("Author", "Jane", false).chunk_type(), *b"tEXt");
+        assert_eq!(TextEntry::new("Author", "Jane", true).chunk_type(), *b"zTXt");
+        assert_eq!(TextEntry::new("Author", "Jürgen", false).chunk_type(), *b"tEXt");
+        assert_eq!(TextEntry::new("Title", "日本", false).chunk_type(), *b"iTXt");
+    }
+
+    #[test]
+    fn test_round_trip_every_kind() {
+        let entries = [
+            TextEntry::new("Author", "Jürgen", false),
+            TextEntry::new("Description", &"a long description ".repeat(20), true),
+            TextEntry::new("Title", "日本の写真", true),
+            TextEntry {
+                keyword: String::from("Copyright"),
+                text: String::from("© 2024"),
+                kind: TextKind::Itxt {
+                    compressed: false,
+                    language_tag: String::from("en-GB"),
+                    translated_keyword: String::from("Copyright"),
+                },
+            },
+        ];
+        for entry in entries.iter() {
+            assert_eq!(&round_trip(entry), entry);
+        }
+    }
+
+    #[test]
+    fn test_ztxt_is_compressed() {
+        let entry = TextEntry::new("Comment", &"x".repeat(1000), true);
+        assert!(entry.to_chunk().unwrap().length() < 100);
+    }
+
+    #[test]
+    fn test_invalid_keyword() {
+        for keyword in ["", " Author", "Two  spaces", "日本", &"k".repeat(80)] {
+            let entry = TextEntry::new(keyword, "value", false);
+            assert!(entry.to_chunk().is_err(), "{:?}", keyword);
+        }
+    }
+
+    #[test]
+    fn test_latin1_kind_rejects_other_text() {
+        let entry = TextEntry {
+            keyword: String::from("Title"),
+            text: String::from("日本"),
+            kind: TextKind::Text,
+        };
+        assert!(matches!(entry.to_chunk(), Err(Error::InvalidChunkData { .. })));
+    }
+
+    #[test]
+    fn test_malformed_chunks() {
+        let cases: [(&str, &[u8]); 4] = [
+            ("tEXt", b"no terminator"),
+            ("zTXt", b"Comment\0\x00not zlib"),
+            ("iTXt", b"Comment\0\x02\x00\0\0text"),
+            ("iTXt", b"Comment\0\x00\x00en\0"),
+        ];
+        for (chunk_type, data) in cases {
+            let chunk = Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec());
+            assert!(TextEntry::try_from(&chunk).is_err(), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn test_raw_keyword() {
+        let chunk = TextEntry::new("Author", "Jane", true).to_chunk().unwrap();
+        assert_eq!(raw_keyword(&chunk), Some(&b"Author"[..]));
+
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"Author\0Jane".to_vec());
+        assert_eq!(raw_keyword(&chunk), None);
+    }
+}
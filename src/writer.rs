@@ -0,0 +1,177 @@
+use std::io::Write;
+
+use crate::chunk::{self, Chunk};
+use crate::chunk_type::ChunkType;
+use crate::error::{Error, Result};
+use crate::png::Png;
+
+/// Writes a PNG one chunk at a time to any `Write` implementation.
+/// The signature is written up front, IHDR has to be the first chunk and
+/// nothing may follow IEND. `finish` appends IEND if it has not been written yet.
+#[derive(Debug)]
+pub struct PngWriter<W: Write> {
+    writer: W,
+    offset: usize,
+    chunks_written: usize,
+    ended: bool,
+}
+
+impl<W: Write> PngWriter<W> {
+    pub fn new(mut writer: W) -> Result<PngWriter<W>> {
+        writer.write_all(&Png::STANDARD_HEADER)?;
+        Ok(PngWriter {
+            writer,
+            offset: Png::STANDARD_HEADER.len(),
+            chunks_written: 0,
+            ended: false,
+        })
+    }
+
+    /// Number of bytes written so far
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Writes an existing chunk byte for byte, keeping its stored CRC
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<()> {
+        self.check_order(chunk.chunk_type())?;
+        self.writer.write_all(&chunk.as_bytes())?;
+        self.advance(chunk.chunk_type(), chunk.length() as usize);
+        Ok(())
+    }
+
+    /// Writes a chunk straight from its type and data, computing the length
+    /// and CRC as it goes instead of building a `Chunk` first
+    pub fn write_data(&mut self, chunk_type: &ChunkType, data: &[u8]) -> Result<()> {
+        self.check_order(chunk_type)?;
+        if data.len() > Chunk::MAX_LENGTH as usize {
+            return Err(Error::LengthTooLarge {
+                length: data.len().min(u32::MAX as usize) as u32,
+                offset: self.offset,
+            });
+        }
+
+        self.writer.write_all(&(data.len() as u32).to_be_bytes())?;
+        self.writer.write_all(&chunk_type.bytes())?;
+        self.writer.write_all(data)?;
+        self.writer.write_all(&chunk::checksum(chunk_type, data).to_be_bytes())?;
+        self.advance(chunk_type, data.len());
+        Ok(())
+    }
+
+    /// Writes IEND if needed, flushes, and hands back the underlying writer
+    pub fn finish(mut self) -> Result<W> {
+        if !self.ended {
+            let iend = ChunkType::try_from(*b"IEND")?;
+            self.write_data(&iend, &[])?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn check_order(&self, chunk_type: &ChunkType) -> Result<()> {
+        let is_ihdr = chunk_type.bytes() == *b"IHDR";
+        let reason = if self.ended {
+            Some("no chunk may follow IEND")
+        } else if self.chunks_written == 0 && !is_ihdr {
+            Some("IHDR must be the first chunk")
+        } else if self.chunks_written > 0 && is_ihdr {
+            Some("IHDR may only appear once, as the first chunk")
+        } else {
+            None
+        };
+
+        match reason {
+            Some(reason) => Err(Error::ChunkOrder {
+                chunk_type: chunk_type.to_string(),
+                reason,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn advance(&mut self, chunk_type: &ChunkType, data_length: usize) {
+        self.offset += 12 + data_length;
+        self.chunks_written += 1;
+        self.ended = chunk_type.bytes() == *b"IEND";
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::ChunkReader;
+    use std::str::FromStr;
+
+    fn ihdr() -> ChunkType {
+        ChunkType::from_str("IHDR").unwrap()
+    }
+
+    #[test]
+    fn test_write_and_read_back() {
+        let mut writer = PngWriter::new(Vec::new()).unwrap();
+        writer.write_data(&ihdr(), &[0; 13]).unwrap();
+        writer
+            .write_data(&ChunkType::from_str("ruSt").unwrap(), b"hidden")
+            .unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let chunks: Vec<Chunk> = ChunkReader::new(bytes.as_slice())
+            .unwrap()
+            .map(|chunk| chunk.unwrap())
+            .collect();
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[1].data(), b"hidden");
+        assert_eq!(chunks[2].chunk_type().to_string(), "IEND");
+    }
+
+    #[test]
+    fn test_write_data_matches_chunk_new() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let expected = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hidden".to_vec());
+
+        let mut writer = PngWriter::new(Vec::new()).unwrap();
+        writer.write_data(&ihdr(), &[0; 13]).unwrap();
+        let start = writer.offset();
+        writer.write_data(&chunk_type, b"hidden").unwrap();
+        let end = writer.offset();
+        let bytes = writer.finish().unwrap();
+
+        assert_eq!(&bytes[start..end], expected.as_bytes().as_slice());
+    }
+
+    #[test]
+    fn test_ihdr_must_come_first() {
+        let mut writer = PngWriter::new(Vec::new()).unwrap();
+        let error = writer
+            .write_data(&ChunkType::from_str("ruSt").unwrap(), b"hidden")
+            .unwrap_err();
+        assert!(matches!(error, Error::ChunkOrder { .. }));
+
+        writer.write_data(&ihdr(), &[0; 13]).unwrap();
+        assert!(writer.write_data(&ihdr(), &[0; 13]).is_err());
+    }
+
+    #[test]
+    fn test_nothing_after_iend() {
+        let mut writer = PngWriter::new(Vec::new()).unwrap();
+        writer.write_data(&ihdr(), &[0; 13]).unwrap();
+        writer.write_data(&ChunkType::from_str("IEND").unwrap(), &[]).unwrap();
+
+        let error = writer
+            .write_data(&ChunkType::from_str("ruSt").unwrap(), b"hidden")
+            .unwrap_err();
+        assert!(matches!(error, Error::ChunkOrder { .. }));
+
+        // finish does not add a second IEND
+        let bytes = writer.finish().unwrap();
+        assert_eq!(bytes.len(), 8 + 25 + 12);
+    }
+
+    #[test]
+    fn test_finish_without_ihdr_fails() {
+        let writer = PngWriter::new(Vec::new()).unwrap();
+        assert!(writer.finish().is_err());
+    }
+}
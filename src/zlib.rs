@@ -0,0 +1,67 @@
+use std::io::{Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+/// Decompresses a zlib stream, giving up once the output would grow past
+/// `limit` bytes so a small hostile stream cannot exhaust memory
+pub(crate) fn inflate(data: &[u8], limit: usize) -> std::result::Result<Vec<u8>, &'static str> {
+    let mut output = Vec::new();
+    ZlibDecoder::new(data)
+        .take(limit as u64 + 1)
+        .read_to_end(&mut output)
+        .map_err(|_| "zlib stream is corrupt")?;
+    if output.len() > limit {
+        return Err("decompressed data is too large");
+    }
+    Ok(output)
+}
+
+pub(crate) fn deflate(data: &[u8]) -> Vec<u8> {
+    deflate_with_level(data, Compression::default().level())
+}
+
+/// Compresses at `level`, from 0 (store only) to 9 (smallest output)
+pub(crate) fn deflate_with_level(data: &[u8], level: u32) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(level.min(9)));
+    encoder
+        .write_all(data)
+        .expect("writing to a Vec cannot fail");
+    encoder.finish().expect("writing to a Vec cannot fail")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let data = b"the same words, the same words, the same words".repeat(10);
+        let compressed = deflate(&data);
+        assert!(compressed.len() < data.len());
+        assert_eq!(inflate(&compressed, data.len()).unwrap(), data);
+    }
+
+    #[test]
+    fn test_levels() {
+        let data = b"abcabcabd".repeat(100);
+        let stored = deflate_with_level(&data, 0);
+        let best = deflate_with_level(&data, 9);
+        assert!(stored.len() > data.len());
+        assert!(best.len() < stored.len());
+        assert_eq!(inflate(&best, data.len()).unwrap(), data);
+    }
+
+    #[test]
+    fn test_limit() {
+        let compressed = deflate(&[0; 1000]);
+        assert!(inflate(&compressed, 999).is_err());
+        assert!(inflate(&compressed, 1000).is_ok());
+    }
+
+    #[test]
+    fn test_corrupt_stream() {
+        assert!(inflate(b"not zlib at all", 100).is_err());
+    }
+}
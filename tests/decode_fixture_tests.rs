@@ -0,0 +1,131 @@
+use std::fs;
+use std::path::Path;
+
+use rust_png_cryptex::image::{ChannelLayout, Image};
+use rust_png_cryptex::Png;
+
+// The fixtures are written by tests/fixtures/generate.py, a small encoder
+// independent of this crate. They are 32x32 images covering every color
+// type and bit depth, plain and Adam7 interlaced, plus palette
+// transparency and tRNS color keys. They are not PngSuite images. Every
+// sample follows `sample`, and palette entries follow `palette_color`,
+// the same formulas the generator uses.
+
+fn sample(x: u32, y: u32, channel: u32, bit_depth: u8) -> u16 {
+    ((x * 2111 + y * 977 + channel * 8191 + x * y * 59) % (1 << bit_depth)) as u16
+}
+
+fn palette_color(index: usize) -> [u8; 3] {
+    [
+        ((index * 37 + 11) % 256) as u8,
+        ((index * 91 + 23) % 256) as u8,
+        ((index * 53 + 101) % 256) as u8,
+    ]
+}
+
+fn decode(name: &str) -> Image {
+    let path = Path::new("tests/fixtures").join(format!("{}.png", name));
+    let bytes = fs::read(&path).unwrap();
+    let png = Png::try_from(bytes.as_slice()).unwrap();
+    let image = png.decode_image().unwrap();
+    assert_eq!((image.width, image.height), (32, 32), "{}", name);
+    image
+}
+
+fn names(kind: &str, bit_depths: &[u8]) -> Vec<(String, u8)> {
+    bit_depths
+        .iter()
+        .flat_map(|&bit_depth| {
+            let name = format!("{}-{}", kind, bit_depth);
+            [(format!("{}-interlaced", name), bit_depth), (name, bit_depth)]
+        })
+        .collect()
+}
+
+/// Checks an image whose samples are stored as is
+fn check_direct(name: &str, layout: ChannelLayout, bit_depth: u8) {
+    let image = decode(name);
+    assert_eq!((image.layout, image.bit_depth), (layout, bit_depth), "{}", name);
+    for y in 0..32 {
+        for x in 0..32 {
+            for channel in 0..layout.channels() {
+                let expected = sample(x, y, channel as u32, bit_depth);
+                assert_eq!(image.sample(x, y, channel), expected, "{} ({}, {})", name, x, y);
+            }
+        }
+    }
+}
+
+/// Checks an indexed image, expanded through the palette and `alphas`
+fn check_indexed(name: &str, bit_depth: u8, alphas: Option<&[u8]>) {
+    let image = decode(name);
+    let layout = match alphas {
+        Some(_) => ChannelLayout::Rgba,
+        None => ChannelLayout::Rgb,
+    };
+    assert_eq!((image.layout, image.bit_depth), (layout, 8), "{}", name);
+    for y in 0..32 {
+        for x in 0..32 {
+            let index = sample(x, y, 0, bit_depth) as usize;
+            let mut expected = palette_color(index).to_vec();
+            if let Some(alphas) = alphas {
+                expected.push(alphas.get(index).copied().unwrap_or(255));
+            }
+            let pixel: Vec<u8> = (0..layout.channels())
+                .map(|channel| image.sample(x, y, channel) as u8)
+                .collect();
+            assert_eq!(pixel, expected, "{} ({}, {})", name, x, y);
+        }
+    }
+}
+
+#[test]
+fn test_grayscale() {
+    for (name, bit_depth) in names("gray", &[1, 2, 4, 8, 16]) {
+        check_direct(&name, ChannelLayout::Gray, bit_depth);
+    }
+}
+
+#[test]
+fn test_grayscale_alpha() {
+    for (name, bit_depth) in names("gray-alpha", &[8, 16]) {
+        check_direct(&name, ChannelLayout::GrayAlpha, bit_depth);
+    }
+}
+
+#[test]
+fn test_rgb() {
+    for (name, bit_depth) in names("rgb", &[8, 16]) {
+        check_direct(&name, ChannelLayout::Rgb, bit_depth);
+    }
+}
+
+#[test]
+fn test_rgba() {
+    for (name, bit_depth) in names("rgba", &[8, 16]) {
+        check_direct(&name, ChannelLayout::Rgba, bit_depth);
+    }
+}
+
+#[test]
+fn test_indexed() {
+    for (name, bit_depth) in names("indexed", &[1, 2, 4, 8]) {
+        check_indexed(&name, bit_depth, None);
+    }
+}
+
+#[test]
+fn test_palette_transparency() {
+    // tRNS covers the first 100 of 256 entries, the rest stay opaque
+    let alphas: Vec<u8> = (0..100).map(|index| (index * 29 % 256) as u8).collect();
+    check_indexed("indexed-8-alpha", 8, Some(&alphas));
+    check_indexed("indexed-8-alpha-interlaced", 8, Some(&alphas));
+    // A single fully transparent entry
+    check_indexed("indexed-4-transparent", 4, Some(&[255, 255, 0]));
+}
+
+#[test]
+fn test_color_key_is_not_applied() {
+    check_direct("gray-8-key", ChannelLayout::Gray, 8);
+    check_direct("rgb-16-key", ChannelLayout::Rgb, 16);
+}
@@ -0,0 +1,96 @@
+"""Writes the decoder test fixtures in this directory.
+
+Run from anywhere with `python3 tests/fixtures/generate.py`; it only needs
+the standard library. It is a deliberately small, independent encoder, so
+the fixtures do not depend on this crate's own encoder. Every image is
+32x32, rows cycle through all five filter types, and the image data is
+split across two IDAT chunks. Samples follow `sample` and palette entries
+follow `palette_color`; tests/decode_fixture_tests.rs checks the decoded
+pixels against the same formulas.
+"""
+import os
+import struct
+import zlib
+
+OUT = os.path.dirname(os.path.abspath(__file__))
+SIZE = 32
+CHANNELS = {0: 1, 2: 3, 3: 1, 4: 2, 6: 4}
+PASSES = [(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)]
+
+def sample(x, y, c, depth):
+    return (x * 2111 + y * 977 + c * 8191 + x * y * 59) % (1 << depth)
+
+def palette_color(i):
+    return bytes(((i * 37 + 11) % 256, (i * 91 + 23) % 256, (i * 53 + 101) % 256))
+
+def chunk(kind, data):
+    body = kind + data
+    return struct.pack(">I", len(data)) + body + struct.pack(">I", zlib.crc32(body))
+
+def pack_row(xs, y, ct, depth):
+    samples = [sample(x, y, c, depth) for x in xs for c in range(CHANNELS[ct])]
+    if depth == 16:
+        return b"".join(struct.pack(">H", s) for s in samples)
+    if depth == 8:
+        return bytes(samples)
+    bits = "".join(format(s, "0%db" % depth) for s in samples)
+    bits += "0" * (-len(bits) % 8)
+    return bytes(int(bits[i:i + 8], 2) for i in range(0, len(bits), 8))
+
+def paeth(a, b, c):
+    p = a + b - c
+    pa, pb, pc = abs(p - a), abs(p - b), abs(p - c)
+    if pa <= pb and pa <= pc:
+        return a
+    return b if pb <= pc else c
+
+def filter_row(kind, line, prev, bpp):
+    out = bytearray()
+    for i, v in enumerate(line):
+        a = line[i - bpp] if i >= bpp else 0
+        b = prev[i]
+        c = prev[i - bpp] if i >= bpp else 0
+        pred = [0, a, b, (a + b) // 2, paeth(a, b, c)][kind]
+        out.append((v - pred) % 256)
+    return bytes([kind]) + bytes(out)
+
+def write(name, ct, depth, interlaced, trns=None):
+    bpp = max(1, CHANNELS[ct] * depth // 8)
+    passes = PASSES if interlaced else [(0, 0, 1, 1)]
+    raw = b""
+    for (x0, y0, dx, dy) in passes:
+        xs = list(range(x0, SIZE, dx))
+        ys = list(range(y0, SIZE, dy))
+        if not xs or not ys:
+            continue
+        prev = bytes(len(pack_row(xs, 0, ct, depth)))
+        for row, y in enumerate(ys):
+            line = pack_row(xs, y, ct, depth)
+            raw += filter_row(row % 5, line, prev, bpp)
+            prev = line
+    ihdr = struct.pack(">IIBBBBB", SIZE, SIZE, depth, ct, 0, 0, 1 if interlaced else 0)
+    data = b"\x89PNG\r\n\x1a\n" + chunk(b"IHDR", ihdr)
+    if ct == 3:
+        data += chunk(b"PLTE", b"".join(palette_color(i) for i in range(1 << depth)))
+    if trns is not None:
+        data += chunk(b"tRNS", trns)
+    compressed = zlib.compress(raw, 9)
+    half = len(compressed) // 2
+    data += chunk(b"IDAT", compressed[:half]) + chunk(b"IDAT", compressed[half:])
+    data += chunk(b"IEND", b"")
+    open(os.path.join(OUT, name + ".png"), "wb").write(data)
+
+KINDS = {0: "gray", 2: "rgb", 3: "indexed", 4: "gray-alpha", 6: "rgba"}
+DEPTHS = {0: [1, 2, 4, 8, 16], 2: [8, 16], 3: [1, 2, 4, 8], 4: [8, 16], 6: [8, 16]}
+for ct, depths in DEPTHS.items():
+    for depth in depths:
+        for interlaced in (False, True):
+            name = "%s-%d%s" % (KINDS[ct], depth, "-interlaced" if interlaced else "")
+            write(name, ct, depth, interlaced)
+
+alphas = bytes((i * 29) % 256 for i in range(100))
+write("indexed-8-alpha", 3, 8, False, alphas)
+write("indexed-8-alpha-interlaced", 3, 8, True, alphas)
+write("indexed-4-transparent", 3, 4, False, bytes([255, 255, 0]))
+write("gray-8-key", 0, 8, False, struct.pack(">H", 42))
+write("rgb-16-key", 2, 16, False, struct.pack(">HHH", 1, 2, 3))